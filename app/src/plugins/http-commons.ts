@@ -3,10 +3,16 @@ import {
     WebsocketNotificationMethod, WebsocketRequestMethod, WebsocketSystemMethod, WsMethods,
 } from "../types/api/WebsocketMethods";
 import {
-    WebsocketError, WebsocketMessage, WebsocketNotification, WebsocketRequest, WebsocketResponse,
+    WebsocketError, WebsocketMessage, WebsocketNotification, WebsocketRequest, WebsocketResponse, WsHelloParams,
 } from "../types/api/WebsocketTypes";
 import {ApiMethod} from "src/types/api/ApiMethods";
 
+const PROTOCOL_VERSION = 1;
+const PROTOCOL_FEATURES = ["cancel", "progress", "topics", "resume"];
+const ACK_INTERVAL = 32;
+const RECONNECT_DELAY = 1000;
+const MAX_RECONNECT_ATTEMPTS = 10;
+
 class AxiosManager {
     axios: AxiosInstance | null;
 
@@ -31,12 +37,22 @@ class AxiosManager {
     init(location: string) {
         this.axios = axios.create({
             baseURL: `http://${location}`,
+            // Send the pairing cookie.
+            withCredentials: true,
             headers: {
                 "Content-type": "application/json",
             },
         });
     }
 
+    setToken(token: string | null) {
+        if (token === null) {
+            delete this.axios!!.defaults.headers["Authorization"];
+        } else {
+            this.axios!!.defaults.headers["Authorization"] = `Bearer ${token}`;
+        }
+    }
+
     clear() {
         this.axios = null;
     }
@@ -53,9 +69,15 @@ class AxiosManager {
 class WebsocketManager {
     scopeManager = new ScopeManager();
     nextId: number;
+    location: string | null;
+    token: string | null;
     websocket: WebSocket | null;
+    session: string | null;
+    lastSeq: number;
+    reconnectAttempts: number;
     sysHandlers: Map<string, ([WebsocketScope, (x: any) => void])[]>;
     requestHandlers: Map<string, ([WebsocketScope, (x: any) => void])[]>;
+    notificationHandlers: Map<string, ([WebsocketScope, (x: any) => void])[]>;
     responseHandlers: Map<string, [WebsocketScope, (x: any) => void]>;
 
     // GETTERS ----------------------------------------------------------------
@@ -68,22 +90,53 @@ class WebsocketManager {
 
     constructor() {
         this.nextId = 0;
+        this.location = null;
+        this.token = null;
         this.websocket = null;
+        this.session = null;
+        this.lastSeq = 0;
+        this.reconnectAttempts = 0;
         this.sysHandlers = new Map<string, [WebsocketScope, ((x: any) => void)][]>();
         this.requestHandlers = new Map<string, [WebsocketScope, ((x: any) => void)][]>();
+        this.notificationHandlers = new Map<string, [WebsocketScope, ((x: any) => void)][]>();
         this.responseHandlers = new Map<string, [WebsocketScope, ((x: any) => void)]>();
     }
 
     // METHODS ----------------------------------------------------------------
 
-    init(location: string) {
-        const socket = new WebSocket(`ws://${location}/ws`);
+    init(location: string, token: string | null = null) {
+        this.location = location;
+        this.token = token;
+        this.connect();
+    }
+
+    private connect() {
+        let params = new URLSearchParams();
+        if (this.token !== null) {
+            params.set("token", this.token);
+        }
+
+        if (this.session !== null) {
+            params.set("session", this.session);
+            params.set("lastSeq", this.lastSeq.toString());
+        }
+
+        let query = params.toString();
+        let url = `ws://${this.location}/ws` + (query.length > 0 ? `?${query}` : "");
+
+        const socket = new WebSocket(url);
         socket.onopen = (event) => {
+            this.reconnectAttempts = 0;
+            this.sendNotification(WsMethods.msg.hello, {
+                version: PROTOCOL_VERSION,
+                features: PROTOCOL_FEATURES,
+            });
+
             this.resolveSystemHandlers(WsMethods.sys.open, event);
         };
 
         socket.onmessage = (event) => {
-            let data: WebsocketMessage<any>;
+            let data: WebsocketMessage<any> | WebsocketMessage<any>[];
             try {
                 data = JSON.parse(event.data);
             } catch (e) {
@@ -91,35 +144,35 @@ class WebsocketManager {
                 return;
             }
 
-            switch (data.type) {
-                case "req":
-                    if (this.requestHandlers.has(data.method)) {
-                        this.resolveRequestHandlers(data);
-                    } else {
-                        this.resolveSystemHandlers(WsMethods.sys.unhandledRequest, data);
-                    }
-                    break;
-                case "res":
-                    if (this.responseHandlers.has(data.id)) {
-                        this.resolveResponseHandlers(data);
-                    } else {
-                        this.resolveSystemHandlers(WsMethods.sys.unhandledResponse, data);
-                    }
-                    break;
-                case "err":
-                    if (!!data.id && this.responseHandlers.has(data.id)) {
-                        this.resolveResponseHandlersWithError(data);
-                    } else {
-                        this.resolveSystemHandlers(WsMethods.sys.unhandledError, data);
-                    }
-                    break;
-                default:
-                    this.resolveSystemHandlers(WsMethods.sys.incorrectMessage, data);
-                    break;
+            // Count every frame but the hello to resume the session later.
+            if (!Array.isArray(data) && data.type === "not" && data.method === WsMethods.msg.hello.name) {
+                this.handleHello(data.params);
+            } else {
+                this.lastSeq++;
+                if (this.lastSeq % ACK_INTERVAL === 0) {
+                    this.sendNotification(WsMethods.msg.ack, {seq: this.lastSeq});
+                }
+            }
+
+            // Batches are received as arrays of messages.
+            if (Array.isArray(data)) {
+                for (let message of data) {
+                    this.handleMessage(message);
+                }
+            } else {
+                this.handleMessage(data);
             }
         };
 
         socket.onclose = (event) => {
+            // Try to resume the session unless the server rejected the client.
+            if (this.session !== null && event.code !== 4000 && event.code !== 4004
+                && this.reconnectAttempts < MAX_RECONNECT_ATTEMPTS) {
+                this.reconnectAttempts++;
+                setTimeout(() => this.connect(), RECONNECT_DELAY);
+                return;
+            }
+
             this.resolveSystemHandlers(WsMethods.sys.close, event);
             this.clear();
         };
@@ -138,10 +191,16 @@ class WebsocketManager {
 
     clear() {
         this.scopeManager.clear();
+        this.location = null;
+        this.token = null;
+        this.session = null;
+        this.lastSeq = 0;
+        this.reconnectAttempts = 0;
         this.websocket?.close();
         this.websocket = null;
         this.sysHandlers.clear();
         this.requestHandlers.clear();
+        this.notificationHandlers.clear();
         this.responseHandlers.clear();
     }
 
@@ -164,6 +223,14 @@ class WebsocketManager {
         this.requestHandlers.set(message.name, handlers);
     }
 
+    onNotification<P>(message: WebsocketNotificationMethod<P>, method: (value: P) => void,
+        scope: WebsocketScope = null) {
+        let handlers = this.notificationHandlers.get(message.name) || [];
+        handlers.push([scope, method]);
+
+        this.notificationHandlers.set(message.name, handlers);
+    }
+
     send<S, R>(method: WebsocketRequestMethod<S, R>, message: S, scope: WebsocketScope = null): Promise<R> {
         let id = this.getNextId();
         let request: WebsocketRequest<S> = {
@@ -181,6 +248,29 @@ class WebsocketManager {
         });
     }
 
+    sendBatch(requests: [WebsocketRequestMethod<any, any>, any][], scope: WebsocketScope = null): Promise<any[]> {
+        let promises: Promise<any>[] = [];
+        let batch: WebsocketRequest<any>[] = [];
+        for (let [method, message] of requests) {
+            let id = this.getNextId();
+            batch.push({
+                type: "req",
+                id: id,
+                method: method.name,
+                params: message,
+            });
+
+            promises.push(new Promise<any>((resolve) => {
+                this.responseHandlers.set(id, [scope, resolve]);
+            }));
+        }
+
+        let data = JSON.stringify(batch);
+        this.websocket!!.send(data);
+
+        return Promise.all(promises);
+    }
+
     sendNotification<S>(method: WebsocketNotificationMethod<S>, message: S) {
         let notification: WebsocketNotification<S> = {
             type: "not",
@@ -203,6 +293,50 @@ class WebsocketManager {
         this.websocket!!.send(data);
     }
 
+    private handleHello(hello: WsHelloParams) {
+        // A new session starts counting frames again.
+        if (!hello.session?.resumed) {
+            this.lastSeq = 0;
+        }
+
+        this.session = hello.session?.token ?? null;
+    }
+
+    private handleMessage(data: WebsocketMessage<any>) {
+        switch (data.type) {
+            case "req":
+                if (this.requestHandlers.has(data.method)) {
+                    this.resolveRequestHandlers(data);
+                } else {
+                    this.resolveSystemHandlers(WsMethods.sys.unhandledRequest, data);
+                }
+                break;
+            case "not":
+                this.resolveNotificationHandlers(data);
+                break;
+            case "res":
+                if (this.responseHandlers.has(data.id)) {
+                    this.resolveResponseHandlers(data);
+                } else {
+                    this.resolveSystemHandlers(WsMethods.sys.unhandledResponse, data);
+                }
+                break;
+            case "prg":
+                this.resolveSystemHandlers(WsMethods.sys.progress, data);
+                break;
+            case "err":
+                if (!!data.id && this.responseHandlers.has(data.id)) {
+                    this.resolveResponseHandlersWithError(data);
+                } else {
+                    this.resolveSystemHandlers(WsMethods.sys.unhandledError, data);
+                }
+                break;
+            default:
+                this.resolveSystemHandlers(WsMethods.sys.incorrectMessage, data);
+                break;
+        }
+    }
+
     private resolveSystemHandlers<T>(message: WebsocketSystemMethod<T>, value: T) {
         let handlers = this.sysHandlers.get(message.name) || [];
         for (let [scope, handler] of handlers) {
@@ -221,6 +355,15 @@ class WebsocketManager {
         }
     }
 
+    private resolveNotificationHandlers(notification: WebsocketNotification<any>) {
+        let handlers = this.notificationHandlers.get(notification.method) || [];
+        for (let [scope, handler] of handlers) {
+            if (this.scopeManager.contains(scope)) {
+                handler(notification.params);
+            }
+        }
+    }
+
     private resolveResponseHandlers(response: WebsocketResponse<any>) {
         let responseHandler = this.responseHandlers.get(response.id);
         if (!responseHandler) {
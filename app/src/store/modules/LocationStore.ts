@@ -16,6 +16,7 @@ export default class LocationStore extends VuexModule {
     ip: string | null = null;
     port: string | null = null;
     version: VersionApiType | null = null;
+    token: string | null = null;
 
     // GETTERS ----------------------------------------------------------------
 
@@ -47,10 +48,15 @@ export default class LocationStore extends VuexModule {
         this.version = version;
     }
 
+    @Mutation setToken(token: string | null) {
+        this.token = token;
+    }
+
     @Mutation clearState() {
         this.ip = null;
         this.port = null;
         this.version = null;
+        this.token = null;
         http.axios.clear();
         http.websocket.clear();
     }
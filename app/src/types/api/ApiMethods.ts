@@ -1,4 +1,4 @@
-import {VersionApiType} from "src/types/api/ApiTypes";
+import {AuthApiType, LoginApiRequest, LoginApiResponse, VersionApiType} from "src/types/api/ApiTypes";
 
 export class ApiMethod<P, R> {
     method: string;
@@ -14,4 +14,7 @@ export class ApiMethod<P, R> {
 
 export const ApiMethods = {
     version: new ApiMethod<undefined, VersionApiType>("GET", "/version"),
+    auth: new ApiMethod<undefined, AuthApiType>("GET", "/auth"),
+    login: new ApiMethod<LoginApiRequest, LoginApiResponse>("POST", "/auth/login"),
+    logout: new ApiMethod<undefined, undefined>("POST", "/auth/logout"),
 };
\ No newline at end of file
@@ -4,4 +4,21 @@ export interface VersionApiType {
     version: string,
     authors: string,
     homepage: string,
-}
\ No newline at end of file
+}
+
+export interface AuthApiType {
+    enabled: boolean,
+}
+
+export interface LoginApiRequest {
+    username: string,
+    password: string,
+}
+
+export type AuthRole = "viewer" | "user" | "admin";
+
+export interface LoginApiResponse {
+    token: string,
+    expiresIn: number,
+    roles: AuthRole[],
+}
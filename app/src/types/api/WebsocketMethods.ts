@@ -1,4 +1,8 @@
-import {WebsocketError, WebsocketMessage, WebsocketRequest, WebsocketResponse} from "src/types/api/WebsocketTypes";
+// Generated by `cargo run --features typescript -- typescript` in `core`. Do not edit.
+
+import {
+    TopicsRequest, WebsocketError, WebsocketMessage, WebsocketProgress, WebsocketRequest, WebsocketResponse, WsAckParams, WsAuthParams, WsCancelParams, WsHelloParams, WsProtocolDescription,
+} from "src/types/api/WebsocketTypes";
 
 export class WebsocketSystemMethod<T> {
     name: string;
@@ -38,12 +42,20 @@ export const WsMethods = {
         unhandledRequest: new WebsocketSystemMethod<WebsocketRequest<any>>("unhandled_request"),
         unhandledResponse: new WebsocketSystemMethod<WebsocketResponse<any>>("unhandled_response"),
         unhandledError: new WebsocketSystemMethod<WebsocketError>("unhandled_error"),
+        progress: new WebsocketSystemMethod<WebsocketProgress<any>>("progress"),
         incorrectMessage: new WebsocketSystemMethod<WebsocketMessage<any>>("incorrect_message"),
     },
     msg: {
-        ping: new WebsocketRequestMethod<undefined, string>("ping"),
         echo: new WebsocketRequestMethod<string, string>("echo"),
+        ping: new WebsocketRequestMethod<undefined, string>("ping"),
+        rpcDiscover: new WebsocketRequestMethod<undefined, WsProtocolDescription>("rpc.discover"),
         shutdown: new WebsocketRequestMethod<undefined, string>("shutdown"),
+        subscribe: new WebsocketRequestMethod<TopicsRequest, string>("subscribe"),
+        unsubscribe: new WebsocketRequestMethod<TopicsRequest, string>("unsubscribe"),
+        ack: new WebsocketNotificationMethod<WsAckParams>("ack"),
         askMe: new WebsocketNotificationMethod<string>("askMe"),
+        auth: new WebsocketNotificationMethod<WsAuthParams>("auth"),
+        cancel: new WebsocketNotificationMethod<WsCancelParams>("cancel"),
+        hello: new WebsocketNotificationMethod<WsHelloParams>("hello"),
     },
-};
\ No newline at end of file
+};
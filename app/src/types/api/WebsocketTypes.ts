@@ -1,5 +1,7 @@
-export type WebsocketMessage<T> = WebsocketRequest<T> | WebsocketResponseOrError<T>
-export type WebsocketResponseOrError<T> = WebsocketResponse<T> | WebsocketError
+// Generated by `cargo run --features typescript -- typescript` in `core`. Do not edit.
+
+export type WebsocketMessage<T> = WebsocketRequest<T> | WebsocketNotification<T> | WebsocketResponseOrError<T>
+export type WebsocketResponseOrError<T> = WebsocketResponse<T> | WebsocketError | WebsocketProgress<T>
 
 export interface WebsocketRequest<T> {
     type: "req",
@@ -20,50 +22,88 @@ export interface WebsocketResponse<T> {
     result: T,
 }
 
+export interface WebsocketProgress<T> {
+    type: "prg",
+    id: string,
+    progress?: number,
+    message?: string,
+    partial?: T,
+}
+
 export interface WebsocketError {
     type: "err",
     id?: string,
-    eid: string,
+    eid: WebsocketErrorId,
     message: string,
+    data?: any,
 }
 
 export function isWebsocketError(obj: any): obj is WebsocketError {
     return obj.type === "err";
 }
 
-// FS -------------------------------------------------------------------------
+// ERRORS ---------------------------------------------------------------------
+
+export type WebsocketErrorId = "ParseError" | "IncorrectInput" | "MethodNotFound" | "InvalidParams" | "Unauthorized" | "Timeout" | "Cancelled" | "Disconnected" | "NotFound" | "Conflict" | "RateLimited" | "Internal";
+
+// PROTOCOL -------------------------------------------------------------------
+
+export interface WsProtocolDescription {
+    version: number,
+    features: string[],
+    requests: WsMethodDescription[],
+    notifications: WsMethodDescription[],
+    serverMessages: WsServerMessageDescription[],
+    definitions: any,
+}
+
+export interface WsMethodDescription {
+    name: string,
+    sequential?: boolean | null,
+    permission?: AuthPermission | null,
+    params: any,
+    result?: any,
+}
+
+export type AuthPermission = "read" | "write" | "admin";
 
-export interface ListFilesWsMessage {
-    folder: string,
-    excludeFiles?: boolean,
-    excludeFolders?: boolean
+export interface WsServerMessageDescription {
+    type: string,
+    method?: string | null,
+    schema: any,
 }
 
-// SETTINGS -------------------------------------------------------------------
+export interface TopicsRequest {
+    topics: string[],
+}
 
-export interface EditSettingsWsMessage {
-    workspace?: string
-    viewSettings?: EditViewSettingsWsMessage
+export interface WsCancelParams {
+    id: string,
 }
 
-export interface EditViewSettingsWsMessage {
-    projectsGridMode?: boolean
+export interface WsHelloParams {
+    version: number,
+    features?: string[],
+    methods?: string[],
+    session?: WsHelloSession | null,
 }
 
-// PROJECTS -------------------------------------------------------------------
+export interface WsHelloSession {
+    token: string,
+    resumed: boolean,
+}
 
-export interface AddProjectWsMessage {
-    name: string
+export interface WsAckParams {
+    seq: number,
 }
 
-export interface EditProjectWsMessage {
-    id: number,
-    name?: string,
-    createdAt?: string,
-    notes?: string,
-    location?: string
+export interface WsAuthParams {
+    token: string,
 }
 
-export interface ProjectIdWsMessage {
-    id: number
-}
\ No newline at end of file
+export interface WsProgress {
+    id: string,
+    progress?: number | null,
+    message?: string | null,
+    partial?: any,
+}
@@ -1,31 +1,172 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use tokio::sync::{mpsc, Mutex};
+use futures::future::AbortHandle;
+use tokio::sync::Mutex;
 use tokio::sync::oneshot::Sender;
 use warp::filters::ws::Message;
 
-use crate::network::ws::messages::{WsError, WsResponse};
+use crate::auth::{AppAuth, AuthIdentity};
+use crate::auth::pairing::AppPairing;
+use crate::network::api::app_files::AppFiles;
+use crate::network::api::origins::AllowedOrigins;
+use crate::network::ws::codec::{WsCodec, WsFrame};
+use crate::network::ws::encoding::WsEncoding;
+use crate::network::ws::errors::{EncodingError, OutboxFullError};
+use crate::network::ws::messages::{WsCloseReason, WsError, WsErrorId, WsHelloParams, WsResponse};
+use crate::network::ws::methods::topics::topic_matches;
+use crate::network::ws::methods::WsMethodRegistry;
+use crate::network::ws::middleware::WsMiddlewareChain;
+use crate::network::ws::outbox::{WsOutbox, WsOutboxPolicy};
+use crate::network::ws::rate_limit::{WsRateLimiter, WsRateLimitPolicy};
+use crate::network::ws::session::WsSession;
 
 pub type AppContextRef = Arc<Mutex<AppContext>>;
 
+pub const DEFAULT_MAX_CLIENTS: usize = 10;
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 16;
+pub const DEFAULT_MAX_PENDING_REQUESTS: usize = 256;
+pub const DEFAULT_MAX_SUBSCRIPTIONS: usize = 64;
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+pub const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(15);
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+pub const DEFAULT_SESSION_GRACE_PERIOD: Duration = Duration::from_secs(30);
+pub const DEFAULT_SESSION_BUFFER_SIZE: usize = 1000;
+pub const DEFAULT_OUTBOX_CAPACITY: usize = 256;
+pub const DEFAULT_OUTBOX_POLICY: WsOutboxPolicy = WsOutboxPolicy::DropOldest;
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+pub const DEFAULT_RATE_LIMIT: &str = "50/100";
+pub const DEFAULT_TOKEN_TTL: Duration = Duration::from_secs(12 * 60 * 60);
+pub const DEFAULT_RATE_LIMIT_POLICY: WsRateLimitPolicy = WsRateLimitPolicy::Reject;
+
+pub type WsAnswerSender = futures::channel::oneshot::Sender<Result<WsResponse, WsError>>;
+pub type WsAnswerReceiver = futures::channel::oneshot::Receiver<Result<WsResponse, WsError>>;
+
 pub struct AppContext {
     // Allows to close the application.
     pub shutdown_trigger: Option<Sender<()>>,
-    pub client: Option<AppClient>,
+    // The request after whose answer the application closes, with the connection it came from.
+    pub shutdown_request: Option<(Arc<String>, Arc<String>)>,
+    // The connected clients by their connection id.
+    pub clients: HashMap<Arc<String>, AppClient>,
+    // The maximum number of clients connected at the same time.
+    pub max_clients: usize,
+    // The maximum number of requests of a client processed at the same time.
+    pub max_concurrent_requests: usize,
+    // The maximum number of requests of a client waiting or being processed at the same time.
+    pub max_pending_requests: usize,
+    // The maximum number of topic patterns each client can be subscribed to.
+    pub max_subscriptions: usize,
+    // The time a client has to answer a request of the server.
+    pub request_timeout: Duration,
+    // The time between two pings sent to each client.
+    pub ping_interval: Duration,
+    // The time without receiving anything from a client after which it is disconnected.
+    pub idle_timeout: Duration,
+    // The time a client has to resume its session after losing the connection.
+    pub session_grace_period: Duration,
+    // The maximum number of frames kept for each client to be replayed.
+    pub session_buffer_size: usize,
+    // The maximum number of frames waiting to be sent to each client.
+    pub outbox_capacity: usize,
+    // What to do when a client has too many frames waiting to be sent.
+    pub outbox_policy: WsOutboxPolicy,
+    // The maximum size in bytes of the messages received from the clients.
+    pub max_message_size: usize,
+    // The methods the clients can call.
+    pub methods: Arc<WsMethodRegistry>,
+    // The hooks that run around every request of the clients.
+    pub middlewares: Arc<WsMiddlewareChain>,
+    // The files of the app served over HTTP, if any.
+    pub app_files: Option<Arc<AppFiles>>,
+    // The users and their tokens, if the clients must authenticate.
+    pub auth: Option<AppAuth>,
+    // The browsers paired with the core, if pairing is required.
+    pub pairing: Option<AppPairing>,
+    // The web pages that can call the API and connect to the websocket.
+    pub allowed_origins: AllowedOrigins,
 }
 
 impl AppContext {
-    pub fn new() -> AppContext {
+    pub fn new(methods: WsMethodRegistry) -> AppContext {
         AppContext {
             shutdown_trigger: None,
-            client: None,
+            shutdown_request: None,
+            clients: HashMap::new(),
+            max_clients: DEFAULT_MAX_CLIENTS,
+            max_concurrent_requests: DEFAULT_MAX_CONCURRENT_REQUESTS,
+            max_pending_requests: DEFAULT_MAX_PENDING_REQUESTS,
+            max_subscriptions: DEFAULT_MAX_SUBSCRIPTIONS,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            ping_interval: DEFAULT_PING_INTERVAL,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            session_grace_period: DEFAULT_SESSION_GRACE_PERIOD,
+            session_buffer_size: DEFAULT_SESSION_BUFFER_SIZE,
+            outbox_capacity: DEFAULT_OUTBOX_CAPACITY,
+            outbox_policy: DEFAULT_OUTBOX_POLICY,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            methods: Arc::new(methods),
+            middlewares: Arc::new(WsMiddlewareChain::new()),
+            app_files: None,
+            auth: None,
+            pairing: None,
+            allowed_origins: AllowedOrigins::List(Default::default()),
         }
     }
 
-    pub fn client_exists(&self) -> bool {
-        return self.client.is_some();
+    pub fn accepts_clients(&self) -> bool {
+        self.clients.len() < self.max_clients
+    }
+
+    pub fn client(&self, connection_id: &Arc<String>) -> Option<&AppClient> {
+        self.clients.get(connection_id)
+    }
+
+    pub fn client_mut(&mut self, connection_id: &Arc<String>) -> Option<&mut AppClient> {
+        self.clients.get_mut(connection_id)
+    }
+
+    /// The connection id of the client whose session has `token`.
+    pub fn session_connection_id(&self, token: &str) -> Option<Arc<String>> {
+        self.clients.iter().find(|(_, client)| client.session.token.as_str() == token).map(|(connection_id, _)| connection_id.clone())
+    }
+
+    /// Whether the client has presented a valid token, or does not need to.
+    pub fn is_authenticated(&self, client: &AppClient) -> bool {
+        match &self.auth {
+            Some(auth) => client.auth.as_ref().is_some_and(|identity| auth.validate(&identity.token).is_some()),
+            None => true,
+        }
+    }
+
+    /// Whether the client can call `method`. Everyone can when authentication is disabled,
+    /// and calls to unknown methods are left to fail later.
+    pub fn is_authorized(&self, client: &AppClient, method: &str) -> bool {
+        let permission = match self.methods.permission(method) {
+            Some(v) => v,
+            None => return true,
+        };
+
+        self.auth.is_none() || client.auth.as_ref().is_some_and(|identity| identity.has_permission(permission))
+    }
+
+    /// Revokes `token` returning whether it was valid. The clients authenticated with it
+    /// are disconnected and must authenticate again to resume their sessions.
+    pub fn logout(&mut self, token: &str) -> bool {
+        let revoked = self.auth.as_mut().is_some_and(|auth| auth.revoke(token));
+        for client in self.clients.values_mut() {
+            if client.auth.as_ref().is_some_and(|identity| identity.token.as_str() == token) {
+                client.auth = None;
+
+                if client.session.is_connected() {
+                    client.close(WsCloseReason::Unauthenticated);
+                }
+            }
+        }
+
+        revoked
     }
 
     pub fn shutdown(&mut self) {
@@ -38,6 +179,103 @@ impl AppContext {
 
 pub struct AppClient {
     pub origin: SocketAddr,
-    pub sender: mpsc::UnboundedSender<std::result::Result<Message, warp::Error>>,
-    pub pending_answers: HashMap<Arc<String>, futures::channel::oneshot::Sender<Result<WsResponse, WsError>>>,
+    // Identifies the websocket the client is currently connected through.
+    pub socket_id: Arc<String>,
+    // Stops listening to that websocket.
+    pub connection_abort: AbortHandle,
+    // The format of the messages exchanged with the client.
+    pub codec: WsCodec,
+    // How those messages are serialized.
+    pub encoding: WsEncoding,
+    // The frames waiting to be sent.
+    pub outbox: WsOutbox,
+    // Whether the client was disconnected because its outbox overflowed.
+    pub overflowed: bool,
+    pub pending_answers: HashMap<Arc<String>, WsAnswerSender>,
+    // The requests of the client that are being processed.
+    pub running_requests: HashMap<Arc<String>, AbortHandle>,
+    // The topic patterns the client is subscribed to.
+    pub subscriptions: HashSet<String>,
+    // What the client announced in its `hello`.
+    pub hello: Option<WsHelloParams>,
+    // When the last frame of the client was received, pongs included.
+    pub last_seen: Instant,
+    // The tokens left to the client to make requests.
+    pub rate_limiter: WsRateLimiter,
+    // Who the client is authenticated as.
+    pub auth: Option<AuthIdentity>,
+    // The frames sent to the client that can be replayed after a reconnection.
+    pub session: WsSession,
+}
+
+impl AppClient {
+    /// Serializes a frame in the format and encoding of the client.
+    pub fn encode(&self, frame: &WsFrame) -> Result<Message, EncodingError> {
+        let value = self.codec.write_frame(frame).map_err(|e| EncodingError { message: e.to_string() })?;
+        self.encoding.encode(&value).map_err(|message| EncodingError { message })
+    }
+
+    /// Queues a frame for the client. Under the `Disconnect` policy, a full outbox closes
+    /// the connection with the client and ends its session.
+    pub fn send(&mut self, msg: Message, is_notification: bool) -> Result<(), OutboxFullError> {
+        let result = self.outbox.push(msg, is_notification);
+
+        if result.is_err() && self.outbox.policy() == WsOutboxPolicy::Disconnect && !self.overflowed {
+            self.overflowed = true;
+            self.outbox.clear();
+            self.close(WsCloseReason::OutboxFull);
+        }
+
+        result
+    }
+
+    /// Closes the connection with the client telling it the reason and stops listening to it.
+    pub fn close(&mut self, reason: WsCloseReason) {
+        debug!("Closing connection {}: {}", self.socket_id, reason.message());
+
+        self.outbox.push_priority(Message::close_with(reason.code(), reason.message()));
+        self.connection_abort.abort();
+    }
+
+    /// Whether the client has not sent anything in `timeout`.
+    pub fn is_idle(&self, timeout: Duration) -> bool {
+        self.last_seen.elapsed() >= timeout
+    }
+
+    /// Whether the client announced support for `feature` in its `hello`.
+    pub fn supports(&self, feature: &str) -> bool {
+        self.hello.as_ref().is_some_and(|hello| hello.features.iter().any(|v| v == feature))
+    }
+
+    pub fn is_subscribed_to(&self, topic: &str) -> bool {
+        self.subscriptions.iter().any(|pattern| topic_matches(pattern, topic))
+    }
+
+    /// Aborts a request of the client returning whether it was running.
+    pub fn cancel_running_request(&mut self, id: &Arc<String>) -> bool {
+        match self.running_requests.remove(id) {
+            Some(abort_handle) => {
+                abort_handle.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Aborts all the requests of the client that are being processed.
+    pub fn cancel_running_requests(&mut self) {
+        for (_, abort_handle) in self.running_requests.drain() {
+            abort_handle.abort();
+        }
+    }
+
+    /// Fails all the requests that are waiting for an answer of the client.
+    pub fn fail_pending_answers(&mut self) {
+        for (id, tx) in self.pending_answers.drain() {
+            let error = WsError::new_string(id, WsErrorId::Disconnected, "Client disconnected".to_string());
+            if let Err(error) = tx.send(Err(error)) {
+                trace!("Ignoring disconnection error because the request was dropped: {:?}", error);
+            }
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,91 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::auth::roles::AuthRole;
+
+// The hash of a random password, with the default cost, checked when the user does not
+// exist so that the time to answer does not tell which users exist.
+const DUMMY_HASH: &str = "$2b$12$bHtuVHPSHsPMwJGNn8O1G.7w2ymCxOaDvdy8hgf3t7nXEdbQG.HV2";
+
+/// The users that can log in, kept in a JSON file like:
+/// `{ "users": { "<name>": { "password": "<bcrypt hash>", "roles": ["admin"] } } }`.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct CredentialStore {
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(default)]
+    users: BTreeMap<String, StoredUser>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct StoredUser {
+    // The bcrypt hash of the password.
+    pub password: String,
+    #[serde(default = "AuthRole::defaults")]
+    pub roles: Vec<AuthRole>,
+}
+
+impl CredentialStore {
+    /// Reads the store at `path`, which is empty if the file does not exist yet.
+    pub fn load(path: PathBuf) -> Result<CredentialStore, Box<dyn Error>> {
+        let mut store: CredentialStore = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => CredentialStore::default(),
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        store.path = path;
+        Ok(store)
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(&self.path, content)?;
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+
+    pub fn user(&self, name: &str) -> Option<&StoredUser> {
+        self.users.get(name)
+    }
+
+    /// Adds a user, with the default roles, or changes its password.
+    pub fn set_password(&mut self, name: &str, password: &str) -> Result<(), Box<dyn Error>> {
+        let password = bcrypt::hash(password, bcrypt::DEFAULT_COST)?;
+        match self.users.get_mut(name) {
+            Some(user) => user.password = password,
+            None => {
+                self.users.insert(name.to_string(), StoredUser {
+                    password,
+                    roles: AuthRole::defaults(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Changes the roles of an existing user.
+    pub fn set_roles(&mut self, name: &str, roles: Vec<AuthRole>) {
+        if let Some(user) = self.users.get_mut(name) {
+            user.roles = roles;
+        }
+    }
+}
+
+/// Whether `password` matches the bcrypt `hash` of a user, which is false if there is no
+/// such user. It is slow on purpose, so it must not run while the context is locked.
+pub fn verify_password(password: &str, hash: Option<&str>) -> bool {
+    let is_valid = bcrypt::verify(password, hash.unwrap_or(DUMMY_HASH)).unwrap_or(false);
+    is_valid && hash.is_some()
+}
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+
+use crate::auth::credentials::CredentialStore;
+use crate::auth::roles::{AuthPermission, AuthRole};
+
+pub mod credentials;
+pub mod pairing;
+pub mod roles;
+
+// The number of characters of the tokens.
+const TOKEN_LENGTH: usize = 40;
+
+/// Who a client is authenticated as.
+#[derive(Debug, Clone)]
+pub struct AuthIdentity {
+    pub user: Arc<String>,
+    // The token the client presented.
+    pub token: Arc<String>,
+    // The roles the user had when the token was issued.
+    pub roles: Arc<Vec<AuthRole>>,
+}
+
+impl AuthIdentity {
+    /// Whether any of the roles grants `permission`.
+    pub fn has_permission(&self, permission: AuthPermission) -> bool {
+        self.roles.iter().any(|role| role.grants(permission))
+    }
+}
+
+struct AuthToken {
+    user: Arc<String>,
+    roles: Arc<Vec<AuthRole>>,
+    expires_at: Instant,
+}
+
+/// The users that can log in and the tokens issued to them.
+pub struct AppAuth {
+    pub credentials: CredentialStore,
+    tokens: HashMap<String, AuthToken>,
+    // The time a token is valid after being issued.
+    pub token_ttl: Duration,
+}
+
+impl AppAuth {
+    pub fn new(credentials: CredentialStore, token_ttl: Duration) -> AppAuth {
+        AppAuth {
+            credentials,
+            tokens: HashMap::new(),
+            token_ttl,
+        }
+    }
+
+    /// Issues a new token for `user` with its `roles`, forgetting the expired ones.
+    pub fn issue_token(&mut self, user: &str, roles: Vec<AuthRole>) -> Arc<String> {
+        let now = Instant::now();
+        self.tokens.retain(|_, token| token.expires_at > now);
+
+        let token = random_token();
+        self.tokens.insert(token.clone(), AuthToken {
+            user: Arc::new(user.to_string()),
+            roles: Arc::new(roles),
+            expires_at: now + self.token_ttl,
+        });
+
+        Arc::new(token)
+    }
+
+    /// The identity of whoever presents `token`, if it is valid.
+    pub fn validate(&self, token: &str) -> Option<AuthIdentity> {
+        let (token, auth_token) = self.tokens.get_key_value(token)?;
+        if auth_token.expires_at <= Instant::now() {
+            return None;
+        }
+
+        Some(AuthIdentity {
+            user: auth_token.user.clone(),
+            token: Arc::new(token.clone()),
+            roles: auth_token.roles.clone(),
+        })
+    }
+
+    /// Invalidates `token` returning whether it existed.
+    pub fn revoke(&mut self, token: &str) -> bool {
+        self.tokens.remove(token).is_some()
+    }
+}
+
+pub fn random_token() -> String {
+    rand::thread_rng().sample_iter(&Alphanumeric).take(TOKEN_LENGTH).collect()
+}
+
+/// Compares two secrets in a time that does not depend on where they differ.
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |result, (a, b)| result | (a ^ b)) == 0
+}
+
+/// Runs the command with the command line arguments after `add-user`:
+/// `<credentials file> <name> [<role>,...]`. The password is read from the first line of
+/// the standard input. Without roles, new users get the default ones and existing users
+/// keep theirs.
+pub fn run_add_user(args: &[String]) {
+    let (path, name, roles) = match args {
+        [path, name] => (PathBuf::from(path), name, None),
+        [path, name, roles] => (PathBuf::from(path), name, Some(roles)),
+        _ => {
+            error!("Usage: add-user <credentials file> <name> [<role>,...]");
+            std::process::exit(1);
+        }
+    };
+
+    let roles = match roles.map(|v| AuthRole::parse_list(v)).transpose() {
+        Ok(v) => v,
+        Err(e) => {
+            error!("{}, expected viewer, user or admin", e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut password = String::new();
+    if let Err(e) = std::io::stdin().lock().read_line(&mut password) {
+        error!("Cannot read the password: {}", e);
+        std::process::exit(1);
+    }
+
+    let password = password.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        error!("The password cannot be empty");
+        std::process::exit(1);
+    }
+
+    let result = CredentialStore::load(path.clone()).and_then(|mut store| {
+        store.set_password(name, password)?;
+        if let Some(roles) = roles {
+            store.set_roles(name, roles);
+        }
+
+        store.save()
+    });
+
+    match result {
+        Ok(_) => info!("Saved user {} in {}", name, path.display()),
+        Err(e) => {
+            error!("Cannot save user {} in {}: {}", name, path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
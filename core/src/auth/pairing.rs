@@ -0,0 +1,96 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::auth::{constant_time_eq, random_token};
+
+/// The name of the cookie that identifies the browsers paired with the core.
+pub const PAIRING_COOKIE: &str = "core_session";
+
+/// The most sessions kept at the same time. Starting another one forgets the oldest.
+const MAX_SESSIONS: usize = 256;
+
+/// Pairs the core with the browsers and clients that present the secret printed at
+/// startup, so that other local processes and web pages cannot talk to it. The secret
+/// is valid until the core stops.
+pub struct AppPairing {
+    secret: String,
+    // The sessions of those that presented it.
+    sessions: HashSet<String>,
+    // The same sessions from the oldest to the newest.
+    session_order: VecDeque<String>,
+}
+
+impl AppPairing {
+    pub fn new() -> AppPairing {
+        AppPairing {
+            secret: random_token(),
+            sessions: HashSet::new(),
+            session_order: VecDeque::new(),
+        }
+    }
+
+    pub fn secret(&self) -> &str {
+        &self.secret
+    }
+
+    /// Starts a new session if `secret` is the secret, returning it. Those that are
+    /// already paired should keep their session instead.
+    pub fn pair(&mut self, secret: &str) -> Option<String> {
+        if !constant_time_eq(&self.secret, secret) {
+            return None;
+        }
+
+        if self.session_order.len() >= MAX_SESSIONS {
+            if let Some(oldest) = self.session_order.pop_front() {
+                self.sessions.remove(&oldest);
+            }
+        }
+
+        let session = random_token();
+        self.sessions.insert(session.clone());
+        self.session_order.push_back(session.clone());
+        Some(session)
+    }
+
+    pub fn is_paired(&self, session: &str) -> bool {
+        self.sessions.contains(session)
+    }
+}
+
+/// The `Set-Cookie` header value of a session.
+pub fn session_cookie(session: &str) -> String {
+    format!("{}={}; Path=/; HttpOnly; SameSite=Strict", PAIRING_COOKIE, session)
+}
+
+#[cfg(test)]
+mod tests {
+    // `tokio` exports its own `test` macro.
+    use std::prelude::v1::test;
+
+    use super::*;
+
+    #[test]
+    fn test_pair() {
+        let mut pairing = AppPairing::new();
+        let secret = pairing.secret().to_string();
+        assert!(pairing.pair("wrong").is_none());
+
+        // The secret can be used more than once.
+        let first = pairing.pair(&secret).unwrap();
+        let second = pairing.pair(&secret).unwrap();
+        assert!(pairing.is_paired(&first));
+        assert!(pairing.is_paired(&second));
+        assert!(!pairing.is_paired("other"));
+    }
+
+    #[test]
+    fn test_oldest_sessions_are_forgotten() {
+        let mut pairing = AppPairing::new();
+        let secret = pairing.secret().to_string();
+        let first = pairing.pair(&secret).unwrap();
+        let sessions: Vec<String> = (0..MAX_SESSIONS).map(|_| pairing.pair(&secret).unwrap()).collect();
+
+        assert!(!pairing.is_paired(&first));
+        assert!(sessions.iter().all(|v| pairing.is_paired(v)));
+        assert_eq!(pairing.sessions.len(), MAX_SESSIONS);
+    }
+}
@@ -0,0 +1,61 @@
+use std::str::FromStr;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// What a request needs to be allowed.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum AuthPermission {
+    // Look at the state of the core.
+    Read,
+    // Change the state of the core.
+    Write,
+    // Manage the core itself, e.g. shut it down.
+    Admin,
+}
+
+/// The roles of a user. Each one grants the permissions of the previous one.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub enum AuthRole {
+    // Only `Read`.
+    Viewer,
+    // `Read` and `Write`.
+    User,
+    // Everything.
+    Admin,
+}
+
+impl AuthRole {
+    /// The roles of the users that do not list theirs.
+    pub fn defaults() -> Vec<AuthRole> {
+        vec![AuthRole::User]
+    }
+
+    pub fn grants(&self, permission: AuthPermission) -> bool {
+        match permission {
+            AuthPermission::Read => true,
+            AuthPermission::Write => *self >= AuthRole::User,
+            AuthPermission::Admin => *self == AuthRole::Admin,
+        }
+    }
+
+    /// Parses a list like `<role>,<role>...`.
+    pub fn parse_list(s: &str) -> Result<Vec<AuthRole>, String> {
+        s.split(',').map(|v| v.trim()).filter(|v| !v.is_empty()).map(|v| v.parse()).collect()
+    }
+}
+
+impl FromStr for AuthRole {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "viewer" => Ok(AuthRole::Viewer),
+            "user" => Ok(AuthRole::User),
+            "admin" => Ok(AuthRole::Admin),
+            _ => Err(format!("Unknown role: {}", s)),
+        }
+    }
+}
@@ -1,7 +1,6 @@
 use std::error::Error;
 use std::fmt::Display;
-
-use serde::export::Formatter;
+use std::fmt::Formatter;
 
 #[derive(Debug)]
 pub struct EmptyError {}
@@ -7,34 +7,145 @@ extern crate log;
 #[macro_use]
 extern crate tokio;
 
+use std::collections::HashMap;
 use std::env;
 use std::error::Error;
 use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use tokio::sync::{Mutex, oneshot};
 
-use crate::application::{AppContext, AppContextRef};
+use crate::application::{AppContext, AppContextRef, DEFAULT_IDLE_TIMEOUT, DEFAULT_MAX_CLIENTS, DEFAULT_MAX_CONCURRENT_REQUESTS, DEFAULT_MAX_PENDING_REQUESTS, DEFAULT_MAX_SUBSCRIPTIONS, DEFAULT_MAX_MESSAGE_SIZE, DEFAULT_OUTBOX_CAPACITY, DEFAULT_OUTBOX_POLICY, DEFAULT_PING_INTERVAL, DEFAULT_RATE_LIMIT, DEFAULT_RATE_LIMIT_POLICY, DEFAULT_REQUEST_TIMEOUT, DEFAULT_SESSION_BUFFER_SIZE, DEFAULT_SESSION_GRACE_PERIOD, DEFAULT_TOKEN_TTL};
+use crate::auth::AppAuth;
+use crate::auth::pairing::AppPairing;
+use crate::auth::credentials::CredentialStore;
 use crate::errors::EmptyError;
+use crate::network::api::app_files::AppFiles;
+use crate::network::api::origins::AllowedOrigins;
+use crate::network::ws::methods::WsMethodRegistry;
+use crate::network::ws::middleware::{WsLoggingMiddleware, WsMiddlewareChain};
+use crate::network::ws::outbox::WsOutboxPolicy;
+use crate::network::ws::rate_limit::{WsRateLimitMiddleware, WsRateLimitPolicy, WsRateLimits};
 
 mod network;
 mod errors;
 mod application;
+mod auth;
 mod utils;
+#[cfg(feature = "typescript")]
+mod typescript;
 
 #[tokio::main]
 async fn main() {
     pretty_env_logger::init();
 
+    // Run a command instead of the server.
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(|v| v.as_str()) {
+        Some("add-user") => {
+            auth::run_add_user(&args[1..]);
+            return;
+        }
+        #[cfg(feature = "typescript")]
+        Some("typescript") => {
+            typescript::run(&args[1..]);
+            return;
+        }
+        _ => {}
+    }
+
     // Read environment variables.
-    let addr: SocketAddr = env::var("ADDR")
-        .unwrap_or_else(|_| "127.0.0.1:21012".to_string()).parse().unwrap_or_else(|_| {
-        error!("A valid address was expected for the ADDR environment variable");
-        panic!();
+    let addr: SocketAddr = env_or("ADDR", SocketAddr::from(([127, 0, 0, 1], 21012)), "A valid address", parsed);
+    let max_clients = env_or("MAX_CLIENTS", DEFAULT_MAX_CLIENTS, "A positive number", positive);
+    let max_concurrent_requests = env_or("MAX_CONCURRENT_REQUESTS", DEFAULT_MAX_CONCURRENT_REQUESTS, "A positive number", positive);
+    let max_pending_requests = env_or("MAX_PENDING_REQUESTS", DEFAULT_MAX_PENDING_REQUESTS, "A positive number", positive);
+    let max_subscriptions = env_or("MAX_SUBSCRIPTIONS", DEFAULT_MAX_SUBSCRIPTIONS, "A positive number", positive);
+    let request_timeout = env_or("REQUEST_TIMEOUT", DEFAULT_REQUEST_TIMEOUT, "A positive number of seconds", seconds);
+    let ping_interval = env_or("PING_INTERVAL", DEFAULT_PING_INTERVAL, "A positive number of seconds", seconds);
+    let idle_timeout = env_or("IDLE_TIMEOUT", DEFAULT_IDLE_TIMEOUT, "A positive number of seconds", seconds);
+
+    // 0 disables resumption.
+    let session_grace_period = env_or("SESSION_GRACE_PERIOD", DEFAULT_SESSION_GRACE_PERIOD, "A number of seconds", |v| parsed(v).map(Duration::from_secs));
+
+    let session_buffer_size = env_or("SESSION_BUFFER_SIZE", DEFAULT_SESSION_BUFFER_SIZE, "A positive number", positive);
+    let outbox_capacity = env_or("OUTBOX_CAPACITY", DEFAULT_OUTBOX_CAPACITY, "A positive number", positive);
+    let outbox_policy: WsOutboxPolicy = env_or("OUTBOX_POLICY", DEFAULT_OUTBOX_POLICY, "One of block, drop-oldest or disconnect", parsed);
+    let max_message_size = env_or("MAX_MESSAGE_SIZE", DEFAULT_MAX_MESSAGE_SIZE, "A positive number of bytes", positive);
+
+    let rate_limit = env_or("RATE_LIMIT", DEFAULT_RATE_LIMIT.parse().ok(), "A limit like <rate>/<burst>, or none,", |v| match v {
+        "none" => Some(None),
+        v => parsed(v).map(Some),
+    });
+
+    let method_rate_limits = env_or("METHOD_RATE_LIMITS", HashMap::new(), "A list like <method>=<rate>/<burst>,...", |v| WsRateLimits::parse_methods(v).ok());
+    let rate_limit_policy: WsRateLimitPolicy = env_or("RATE_LIMIT_POLICY", DEFAULT_RATE_LIMIT_POLICY, "One of reject or disconnect", parsed);
+
+    #[cfg(feature = "embedded-app")]
+    let default_app_files = Some(AppFiles::Embedded);
+    #[cfg(not(feature = "embedded-app"))]
+    let default_app_files = None;
+
+    let app_files = env_or("APP_DIR", default_app_files, "A directory", |v| {
+        let dir = PathBuf::from(v);
+        if dir.is_dir() { Some(Some(AppFiles::Directory(dir))) } else { None }
     });
 
+    let token_ttl = env_or("TOKEN_TTL", DEFAULT_TOKEN_TTL, "A positive number of seconds", seconds);
+
+    let auth = match env::var("CREDENTIALS_FILE") {
+        Ok(v) => {
+            let credentials = CredentialStore::load(PathBuf::from(&v)).unwrap_or_else(|e| {
+                error!("Cannot read the credentials file {}: {}", v, e);
+                panic!();
+            });
+
+            if credentials.is_empty() {
+                warn!("The credentials file {} has no users, add them with `core add-user`", v);
+            }
+
+            Some(AppAuth::new(credentials, token_ttl))
+        }
+        Err(_) => {
+            warn!("Authentication is disabled, set the CREDENTIALS_FILE environment variable to enable it");
+            None
+        }
+    };
+
+    // Only the browsers that open the served app can present the pairing cookie.
+    let pairing = env_or("PAIRING", app_files.is_some(), "Either true or false", parsed);
+    let allowed_origins: AllowedOrigins = env_or("ALLOWED_ORIGINS", AllowedOrigins::for_address(&addr), "A list like http://localhost:8080,... or *", parsed);
+
     // Initiate context.
-    let mut context = AppContext::new();
+    let rate_limits = WsRateLimits {
+        global: rate_limit,
+        methods: method_rate_limits,
+    };
+
+    let mut middlewares = WsMiddlewareChain::new();
+    middlewares.add(WsLoggingMiddleware);
+    middlewares.add(WsRateLimitMiddleware::new(rate_limits, rate_limit_policy));
+
+    let mut context = AppContext::new(WsMethodRegistry::with_defaults());
+    context.middlewares = Arc::new(middlewares);
+    context.max_clients = max_clients;
+    context.max_concurrent_requests = max_concurrent_requests;
+    context.max_pending_requests = max_pending_requests;
+    context.max_subscriptions = max_subscriptions;
+    context.request_timeout = request_timeout;
+    context.ping_interval = ping_interval;
+    context.idle_timeout = idle_timeout;
+    context.session_grace_period = session_grace_period;
+    context.session_buffer_size = session_buffer_size;
+    context.outbox_capacity = outbox_capacity;
+    context.outbox_policy = outbox_policy;
+    context.max_message_size = max_message_size;
+    context.app_files = app_files.map(Arc::new);
+    context.auth = auth;
+    context.pairing = if pairing { Some(AppPairing::new()) } else { None };
+    context.allowed_origins = allowed_origins;
     let (sender, receiver) = oneshot::channel();
     context.shutdown_trigger = Some(sender);
     let context_ref: AppContextRef = Arc::new(Mutex::new(context));
@@ -63,10 +174,31 @@ async fn main() {
         result
     };
 
-    match try_join!(api_future, shutdown_future) {
-        Ok(_) => {}
-        Err(_) => {}
-    };
+    let _ = try_join!(api_future, shutdown_future);
 
     info!("Good bye!");
+}
+
+/// Reads the environment variable `name` with `parse`, or returns `default` if it is not set.
+/// The core does not start with an invalid value, described by `expected` in the error.
+fn env_or<T>(name: &str, default: T, expected: &str, parse: impl FnOnce(&str) -> Option<T>) -> T {
+    match env::var(name) {
+        Ok(v) => parse(&v).unwrap_or_else(|| {
+            error!("{} was expected for the {} environment variable", expected, name);
+            panic!();
+        }),
+        Err(_) => default,
+    }
+}
+
+fn parsed<T: FromStr>(v: &str) -> Option<T> {
+    v.parse().ok()
+}
+
+fn positive<T: FromStr + PartialOrd + Default>(v: &str) -> Option<T> {
+    parsed(v).filter(|v| *v > T::default())
+}
+
+fn seconds(v: &str) -> Option<Duration> {
+    positive(v).map(Duration::from_secs)
 }
\ No newline at end of file
@@ -0,0 +1,92 @@
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Component, Path, PathBuf};
+
+use warp::http::header::{CACHE_CONTROL, CONTENT_TYPE, ETAG};
+use warp::http::{Response, StatusCode};
+use warp::hyper::Body;
+
+#[cfg(feature = "embedded-app")]
+static EMBEDDED_APP: include_dir::Dir<'static> = include_dir::include_dir!("$CARGO_MANIFEST_DIR/../app/dist/spa");
+
+pub const INDEX_FILE: &str = "index.html";
+
+// The files with a hash in their name never change.
+const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+// Any other file is revalidated every time it is used.
+const REVALIDATE_CACHE_CONTROL: &str = "no-cache";
+// The first segments of the paths of the API, which are never served from the app.
+const API_PREFIXES: [&str; 4] = ["auth", "metrics", "version", "ws"];
+
+/// Where the files of the built app are served from.
+pub enum AppFiles {
+    // A directory with the output of `quasar build`.
+    Directory(PathBuf),
+    // The app built with the binary.
+    #[cfg(feature = "embedded-app")]
+    Embedded,
+}
+
+impl AppFiles {
+    /// The file at `path` with its content. Paths that do not exist and do not look like
+    /// a file are routes of the app, so they get the `index.html`.
+    pub async fn resolve<'a>(&self, path: &'a str) -> Option<(&'a str, Cow<'static, [u8]>)> {
+        let path = if path.is_empty() { INDEX_FILE } else { path };
+
+        if path.split('/').next().is_some_and(|v| API_PREFIXES.contains(&v)) {
+            return None;
+        }
+
+        // Do not let the requests escape the app.
+        if !Path::new(path).components().all(|v| matches!(v, Component::Normal(_))) {
+            return None;
+        }
+
+        if let Some(content) = self.read(path).await {
+            return Some((path, content));
+        }
+
+        let is_route = path.rsplit('/').next().is_some_and(|name| !name.contains('.'));
+        if !is_route {
+            return None;
+        }
+
+        self.read(INDEX_FILE).await.map(|content| (INDEX_FILE, content))
+    }
+
+    async fn read(&self, path: &str) -> Option<Cow<'static, [u8]>> {
+        match self {
+            AppFiles::Directory(dir) => tokio::fs::read(dir.join(path)).await.ok().map(Cow::Owned),
+            #[cfg(feature = "embedded-app")]
+            AppFiles::Embedded => EMBEDDED_APP.get_file(path).map(|file| Cow::Borrowed(file.contents())),
+        }
+    }
+}
+
+/// Builds the response for a file of the app, or a `304 Not Modified` if the client
+/// already has it.
+pub fn file_response(path: &str, content: Cow<'static, [u8]>, if_none_match: Option<String>) -> Response<Body> {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    let etag = format!("\"{:016x}\"", hasher.finish());
+
+    let cache_control = if is_hashed(path) { IMMUTABLE_CACHE_CONTROL } else { REVALIDATE_CACHE_CONTROL };
+    let builder = Response::builder().header(CACHE_CONTROL, cache_control).header(ETAG, etag.as_str());
+
+    let response = if if_none_match.as_deref() == Some(etag.as_str()) {
+        builder.status(StatusCode::NOT_MODIFIED).body(Body::empty())
+    } else {
+        let mime = mime_guess::from_path(path).first_or_octet_stream();
+        builder.header(CONTENT_TYPE, mime.as_ref()).body(Body::from(content))
+    };
+
+    response.unwrap()
+}
+
+/// Whether the name of the file contains a hash like `app.1a2b3c4d.js`.
+fn is_hashed(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let parts: Vec<&str> = name.split('.').collect();
+    parts.len() > 2 && parts[1..parts.len() - 1].iter().any(|v| v.len() >= 8 && v.chars().all(|c| c.is_ascii_hexdigit()))
+}
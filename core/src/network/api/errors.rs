@@ -1,11 +1,36 @@
 use warp::reject::Reject;
 
 #[derive(Debug)]
-pub struct ConnectionAlreadyInUse;
+pub struct TooManyConnections;
 
-impl Reject for ConnectionAlreadyInUse {}
+impl Reject for TooManyConnections {}
 
 #[derive(Debug)]
 pub struct OriginAddressRequired;
 
-impl Reject for OriginAddressRequired {}
\ No newline at end of file
+impl Reject for OriginAddressRequired {}
+
+#[derive(Debug)]
+pub struct Unauthenticated;
+
+impl Reject for Unauthenticated {}
+
+#[derive(Debug)]
+pub struct InvalidCredentials;
+
+impl Reject for InvalidCredentials {}
+
+#[derive(Debug)]
+pub struct PairingRequired;
+
+impl Reject for PairingRequired {}
+
+#[derive(Debug)]
+pub struct AuthenticationDisabled;
+
+impl Reject for AuthenticationDisabled {}
+
+#[derive(Debug)]
+pub struct OriginNotAllowed;
+
+impl Reject for OriginNotAllowed {}
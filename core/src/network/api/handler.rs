@@ -1,15 +1,27 @@
 use std::convert::Infallible;
 use std::error::Error;
 use std::net::SocketAddr;
+use std::sync::Arc;
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use warp::{Rejection, Reply, reply::json};
-use warp::http::StatusCode;
+use warp::http::{header, HeaderValue, StatusCode};
+use warp::path::{FullPath, Tail};
 
 use crate::application::AppContextRef;
-use crate::network::api::errors::{ConnectionAlreadyInUse, OriginAddressRequired};
-use crate::network::ws::websocket_connection;
+use crate::auth::AuthIdentity;
+use crate::auth::credentials::verify_password;
+use crate::auth::pairing::session_cookie;
+use crate::auth::roles::AuthRole;
+use crate::network::api::app_files::{AppFiles, file_response};
+use crate::network::api::errors::{AuthenticationDisabled, InvalidCredentials, OriginAddressRequired, OriginNotAllowed, PairingRequired, TooManyConnections, Unauthenticated};
+use crate::network::api::origins::AllowedOrigins;
+use crate::network::ws::codec::WsCodec;
+use crate::network::ws::encoding::WsEncoding;
+use crate::network::ws::protocol::describe_protocol;
+use crate::network::ws::session::WsResumeParams;
+use crate::network::ws::{websocket_connection, WsHandshake};
 
 pub async fn version_handler() -> Result<impl Reply, Rejection> {
     Ok(json(&VersionResponse {
@@ -21,10 +33,178 @@ pub async fn version_handler() -> Result<impl Reply, Rejection> {
     }))
 }
 
-pub async fn ws_handler(ws: warp::ws::Ws, origin: Option<SocketAddr>, context_ref: AppContextRef) -> Result<impl Reply, Rejection> {
-    // Prevent connection if there is already one.
-    if context_ref.lock().await.client_exists() {
-        Err(warp::reject::custom(ConnectionAlreadyInUse {}))
+pub async fn check_origin(origin: Option<String>, allowed_origins: Arc<AllowedOrigins>) -> Result<(), Rejection> {
+    match origin {
+        Some(origin) if !allowed_origins.allows(&origin) => {
+            warn!("Rejected a request from the origin {}", origin);
+            Err(warp::reject::custom(OriginNotAllowed {}))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Pairs the browser that opens the URL with the pairing secret, redirecting it to the same
+/// path without the secret. Browsers that are already paired keep their session. Websocket
+/// upgrades are paired by `check_pairing` instead because they cannot be redirected.
+pub async fn pairing_handler(upgrade: Option<String>, session: Option<String>, path: FullPath, query: PairingQuery, context_ref: AppContextRef) -> Result<impl Reply, Rejection> {
+    let secret = match (upgrade, query.pairing) {
+        (None, Some(secret)) => secret,
+        _ => return Err(warp::reject::not_found()),
+    };
+
+    let session = {
+        let mut context = context_ref.lock().await;
+        let pairing = context.pairing.as_mut().ok_or_else(warp::reject::not_found)?;
+        match session {
+            Some(session) if pairing.is_paired(&session) => None,
+            _ => {
+                let session = pairing.pair(&secret).ok_or_else(|| warp::reject::custom(PairingRequired {}))?;
+                info!("Paired with a new browser");
+                Some(session)
+            }
+        }
+    };
+
+    let location = HeaderValue::from_str(path.as_str()).map_err(|_| warp::reject::not_found())?;
+    let mut response = warp::reply::with_status(warp::reply(), StatusCode::SEE_OTHER).into_response();
+    response.headers_mut().insert(header::LOCATION, location);
+    Ok(with_session_cookie(session, response))
+}
+
+pub async fn check_pairing(session: Option<String>, query: PairingQuery, context_ref: AppContextRef) -> Result<Option<String>, Rejection> {
+    let mut context = context_ref.lock().await;
+    let pairing = match context.pairing.as_mut() {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+
+    if session.is_some_and(|session| pairing.is_paired(&session)) {
+        return Ok(None);
+    }
+
+    match query.pairing.and_then(|secret| pairing.pair(&secret)) {
+        Some(session) => {
+            info!("Paired with a new client");
+            Ok(Some(session))
+        }
+        None => Err(warp::reject::custom(PairingRequired {})),
+    }
+}
+
+/// Sets the cookie of a new session in the response.
+pub fn with_session_cookie<R: Reply>(session: Option<String>, reply: R) -> warp::reply::Response {
+    let mut response = reply.into_response();
+    if let Some(session) = session {
+        if let Ok(value) = HeaderValue::from_str(&session_cookie(&session)) {
+            response.headers_mut().insert(header::SET_COOKIE, value);
+        }
+    }
+
+    response
+}
+
+pub async fn authenticate(authorization: Option<String>, context_ref: AppContextRef) -> Result<Option<AuthIdentity>, Rejection> {
+    let context = context_ref.lock().await;
+    let auth = match &context.auth {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+
+    let token = authorization.as_deref().and_then(|v| v.strip_prefix("Bearer "));
+    match token.and_then(|token| auth.validate(token)) {
+        Some(identity) => Ok(Some(identity)),
+        None => Err(warp::reject::custom(Unauthenticated {})),
+    }
+}
+
+pub async fn auth_handler(context_ref: AppContextRef) -> Result<impl Reply, Rejection> {
+    let enabled = context_ref.lock().await.auth.is_some();
+    Ok(json(&AuthResponse { enabled }))
+}
+
+pub async fn login_handler(request: LoginRequest, context_ref: AppContextRef) -> Result<impl Reply, Rejection> {
+    let user = {
+        let context = context_ref.lock().await;
+        let auth = context.auth.as_ref().ok_or_else(|| warp::reject::custom(AuthenticationDisabled {}))?;
+        auth.credentials.user(&request.username).cloned()
+    };
+
+    // Check the password outside the lock because it is slow.
+    let password = request.password;
+    let hash = user.as_ref().map(|user| user.password.clone());
+    let is_valid = tokio::task::spawn_blocking(move || verify_password(&password, hash.as_deref())).await.unwrap_or(false);
+
+    let user = match user.filter(|_| is_valid) {
+        Some(v) => v,
+        None => {
+            warn!("Failed login attempt of {}", request.username);
+            return Err(warp::reject::custom(InvalidCredentials {}));
+        }
+    };
+
+    let mut context = context_ref.lock().await;
+    let auth = context.auth.as_mut().ok_or_else(|| warp::reject::custom(AuthenticationDisabled {}))?;
+    let token = auth.issue_token(&request.username, user.roles.clone());
+    info!("User {} logged in", request.username);
+
+    Ok(json(&LoginResponse {
+        token: token.to_string(),
+        expires_in: auth.token_ttl.as_secs(),
+        roles: user.roles,
+    }))
+}
+
+pub async fn logout_handler(identity: Option<AuthIdentity>, context_ref: AppContextRef) -> Result<impl Reply, Rejection> {
+    let identity = identity.ok_or_else(|| warp::reject::custom(AuthenticationDisabled {}))?;
+    context_ref.lock().await.logout(&identity.token);
+    info!("User {} logged out", identity.user);
+
+    Ok(warp::reply::with_status(warp::reply(), StatusCode::NO_CONTENT))
+}
+
+pub async fn metrics_handler(_identity: Option<AuthIdentity>, context_ref: AppContextRef) -> Result<impl Reply, Rejection> {
+    let context = context_ref.lock().await;
+    let mut clients: Vec<ClientMetrics> = context.clients.iter().map(|(connection_id, client)| ClientMetrics {
+        connection_id: connection_id.to_string(),
+        connected: client.session.is_connected(),
+        queue_depth: client.outbox.depth(),
+        max_queue_depth: client.outbox.max_depth(),
+        dropped_frames: client.outbox.dropped(),
+    }).collect();
+    clients.sort_by(|a, b| a.connection_id.cmp(&b.connection_id));
+
+    Ok(json(&MetricsResponse {
+        queue_capacity: context.outbox_capacity,
+        clients,
+    }))
+}
+
+pub async fn ws_schema_handler(_identity: Option<AuthIdentity>, context_ref: AppContextRef) -> Result<impl Reply, Rejection> {
+    let methods = context_ref.lock().await.methods.clone();
+    Ok(json(&describe_protocol(&methods)))
+}
+
+pub async fn ws_handler(ws: warp::ws::Ws, origin: Option<SocketAddr>, query: WsConnectionQuery, codec: WsCodec, context_ref: AppContextRef) -> Result<impl Reply, Rejection> {
+    let last_seq = query.last_seq.unwrap_or(0);
+    let resume = query.session.map(|token| WsResumeParams { token, last_seq });
+
+    // Prevent connection if the maximum number of clients has been reached,
+    // unless the client resumes its session.
+    let (accepts_client, identity, max_message_size) = {
+        let context = context_ref.lock().await;
+        let accepts_client = context.accepts_clients() || resume.as_ref().is_some_and(|resume| context.session_connection_id(&resume.token).is_some());
+
+        // Clients without a token must authenticate with their first message.
+        let identity = match (&context.auth, &query.token) {
+            (Some(auth), Some(token)) => Some(auth.validate(token).ok_or_else(|| warp::reject::custom(Unauthenticated {}))?),
+            _ => None,
+        };
+
+        (accepts_client, identity, context.max_message_size)
+    };
+
+    if !accepts_client {
+        Err(warp::reject::custom(TooManyConnections {}))
     } else {
         let origin = match origin {
             None => {
@@ -33,11 +213,41 @@ pub async fn ws_handler(ws: warp::ws::Ws, origin: Option<SocketAddr>, context_re
             Some(v) => v
         };
 
-        let connection_id = Uuid::new_v4().to_hyphenated().to_string();
-        Ok(ws.on_upgrade(move |socket| websocket_connection(socket, connection_id, origin, context_ref)))
+        let encoding = query.encoding.unwrap_or_default();
+        let socket_id = Uuid::new_v4().to_hyphenated().to_string();
+        let handshake = WsHandshake {
+            origin,
+            codec,
+            encoding,
+            resume,
+            identity,
+        };
+
+        // Bigger messages are rejected before being buffered.
+        let ws = ws.max_message_size(max_message_size).max_frame_size(max_message_size);
+        Ok(ws.on_upgrade(move |socket| websocket_connection(socket, socket_id, handshake, context_ref)))
     }
 }
 
+/// Serves the files of the app, if any.
+pub async fn app_handler(tail: Tail, if_none_match: Option<String>, app_files: Option<Arc<AppFiles>>) -> Result<impl Reply, Rejection> {
+    let app_files = app_files.ok_or_else(warp::reject::not_found)?;
+    match app_files.resolve(tail.as_str()).await {
+        Some((path, content)) => Ok(file_response(path, content, if_none_match)),
+        None => Err(warp::reject::not_found()),
+    }
+}
+
+/// Answers the rejections of the API but those of paths it does not have, which are
+/// left to the app.
+pub(crate) async fn handle_api_rejection(err: Rejection) -> Result<impl Reply, Rejection> {
+    if err.is_not_found() {
+        return Err(err);
+    }
+
+    handle_rejection(err).await.map_err(|e| match e {})
+}
+
 // This function receives a `Rejection` and tries to return a custom
 // value, otherwise simply passes the rejection along.
 pub(crate) async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible> {
@@ -47,12 +257,33 @@ pub(crate) async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infal
     if err.is_not_found() {
         code = StatusCode::NOT_FOUND;
         message = "NOT_FOUND";
-    } else if let Some(_e) = err.find::<ConnectionAlreadyInUse>() {
-        code = StatusCode::BAD_REQUEST;
-        message = "CONNECTION_ALREADY_IN_USE";
+    } else if let Some(_e) = err.find::<TooManyConnections>() {
+        code = StatusCode::SERVICE_UNAVAILABLE;
+        message = "TOO_MANY_CONNECTIONS";
+    } else if let Some(_e) = err.find::<PairingRequired>() {
+        code = StatusCode::UNAUTHORIZED;
+        message = "PAIRING_REQUIRED";
+    } else if let Some(_e) = err.find::<Unauthenticated>() {
+        code = StatusCode::UNAUTHORIZED;
+        message = "UNAUTHENTICATED";
+    } else if let Some(_e) = err.find::<InvalidCredentials>() {
+        code = StatusCode::UNAUTHORIZED;
+        message = "INVALID_CREDENTIALS";
+    } else if let Some(_e) = err.find::<AuthenticationDisabled>() {
+        code = StatusCode::NOT_FOUND;
+        message = "AUTHENTICATION_DISABLED";
+    } else if let Some(_e) = err.find::<OriginNotAllowed>() {
+        code = StatusCode::FORBIDDEN;
+        message = "ORIGIN_NOT_ALLOWED";
+    } else if let Some(_e) = err.find::<warp::filters::cors::CorsForbidden>() {
+        code = StatusCode::FORBIDDEN;
+        message = "CORS_FORBIDDEN";
     } else if let Some(_e) = err.find::<OriginAddressRequired>() {
         code = StatusCode::BAD_REQUEST;
         message = "ORIGIN_ADDRESS_REQUIRED";
+    } else if err.find::<warp::reject::InvalidQuery>().is_some() {
+        code = StatusCode::BAD_REQUEST;
+        message = "INVALID_QUERY";
     } else if let Some(e) = err.find::<warp::filters::body::BodyDeserializeError>() {
         // This error happens if the body could not be deserialized correctly
         // We can use the cause to analyze the error and customize the error message
@@ -96,6 +327,66 @@ struct VersionResponse {
     homepage: &'static str,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct PairingQuery {
+    // The secret printed at startup.
+    pairing: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct AuthResponse {
+    // Whether the clients must log in.
+    enabled: bool,
+}
+
+// Not `Debug` to keep the password out of the logs.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LoginRequest {
+    username: String,
+    password: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LoginResponse {
+    token: String,
+    // The seconds until the token expires.
+    expires_in: u64,
+    roles: Vec<AuthRole>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct MetricsResponse {
+    queue_capacity: usize,
+    clients: Vec<ClientMetrics>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ClientMetrics {
+    connection_id: String,
+    connected: bool,
+    // The frames waiting to be sent.
+    queue_depth: usize,
+    max_queue_depth: usize,
+    // The notifications dropped because the queue was full.
+    dropped_frames: u64,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WsConnectionQuery {
+    encoding: Option<WsEncoding>,
+    // The token of the session to resume.
+    session: Option<String>,
+    // The sequence number of the last frame received in that session.
+    last_seq: Option<u64>,
+    // The token received when logging in.
+    token: Option<String>,
+}
+
 #[derive(Serialize, Debug)]
 struct ErrorResponse {
     code: u16,
@@ -1,20 +1,30 @@
 use std::convert::Infallible;
 use std::error::Error;
 use std::net::SocketAddr;
+use std::sync::Arc;
 
-use warp::{Filter, Future};
+use warp::{Filter, Future, Rejection, Reply};
 
 use crate::application::AppContextRef;
+use crate::auth::AuthIdentity;
+use crate::auth::pairing::PAIRING_COOKIE;
 use crate::errors::EmptyError;
+use crate::network::api::app_files::AppFiles;
+use crate::network::api::origins::AllowedOrigins;
+use crate::network::ws::codec::WsCodec;
 
+pub mod app_files;
+pub mod origins;
 mod handler;
 mod errors;
 
 pub async fn init_api(addr: &SocketAddr, context: AppContextRef) -> Result<impl Future<Output = ()>, Box<dyn Error>> {
-    // Create routes.
-    let version_route = warp::path!("version").and_then(handler::version_handler);
-    let ws_route = warp::path("ws").and(warp::ws()).and(warp::addr::remote()).and(with_context(context)).and_then(handler::ws_handler);
-    let routes = version_route.or(ws_route).with(warp::cors().allow_any_origin()).recover(handler::handle_rejection);
+    let (app_files, pairing_secret, allowed_origins) = {
+        let context = context.lock().await;
+        (context.app_files.clone(), context.pairing.as_ref().map(|v| v.secret().to_string()), context.allowed_origins.clone())
+    };
+
+    let routes = routes(context, app_files, allowed_origins);
 
     // Init server.
     let (addr, server) = match warp::serve(routes).try_bind_ephemeral((addr.ip(), addr.port())) {
@@ -27,9 +37,117 @@ pub async fn init_api(addr: &SocketAddr, context: AppContextRef) -> Result<impl
 
     info!("Listening on http://{}", addr);
 
+    // Printed regardless of the log level because it is needed to use the core.
+    if let Some(secret) = pairing_secret {
+        let host = if addr.ip().is_unspecified() { format!("localhost:{}", addr.port()) } else { addr.to_string() };
+        println!("To pair with the core, open http://{}/?pairing={}", host, secret);
+    }
+
     Ok(server)
 }
 
+fn routes(context: AppContextRef, app_files: Option<Arc<AppFiles>>, allowed_origins: AllowedOrigins) -> impl Filter<Extract = impl Reply, Error = Infallible> + Clone {
+    // Create routes.
+    let pairing_route = warp::get().and(warp::header::optional("upgrade")).and(warp::cookie::optional(PAIRING_COOKIE)).and(warp::path::full()).and(warp::query()).and(with_context(context.clone())).and_then(handler::pairing_handler);
+    let version_route = warp::path!("version").and_then(handler::version_handler);
+    let auth_route = warp::path!("auth").and(warp::get()).and(with_context(context.clone())).and_then(handler::auth_handler);
+    let login_route = warp::path!("auth" / "login").and(warp::post()).and(warp::body::json()).and(with_context(context.clone())).and_then(handler::login_handler);
+    let logout_route = warp::path!("auth" / "logout").and(warp::post()).and(with_auth(context.clone())).and(with_context(context.clone())).and_then(handler::logout_handler);
+    let metrics_route = warp::path!("metrics").and(with_auth(context.clone())).and(with_context(context.clone())).and_then(handler::metrics_handler);
+    let ws_schema_route = warp::path!("ws" / "schema").and(with_auth(context.clone())).and(with_context(context.clone())).and_then(handler::ws_schema_handler);
+    let ws_route = warp::path!("ws").and(warp::ws()).and(warp::addr::remote()).and(warp::query()).and(with_codec(WsCodec::Native)).and(with_context(context.clone())).and_then(handler::ws_handler);
+    let ws_jsonrpc_route = warp::path!("ws" / "jsonrpc").and(warp::ws()).and(warp::addr::remote()).and(warp::query()).and(with_codec(WsCodec::JsonRpc)).and(with_context(context.clone())).and_then(handler::ws_handler);
+    let app_route = warp::get().and(warp::path::tail()).and(warp::header::optional("if-none-match")).and(with_app_files(app_files)).and_then(handler::app_handler);
+    let api_routes = version_route.or(auth_route).or(login_route).or(logout_route).or(metrics_route).or(ws_schema_route).or(ws_route).or(ws_jsonrpc_route);
+    // The app only gets the paths the API does not have.
+    let api_routes = api_routes.recover(handler::handle_api_rejection).or(app_route);
+    let cors = allowed_origins.cors();
+    let routes = pairing_route.or(with_pairing(context).and(api_routes).map(handler::with_session_cookie)).with(cors);
+    with_origin(Arc::new(allowed_origins)).and(routes).recover(handler::handle_rejection)
+}
+
+fn with_codec(codec: WsCodec) -> impl Filter<Extract = (WsCodec, ), Error = Infallible> + Clone {
+    warp::any().map(move || codec)
+}
+
+fn with_app_files(app_files: Option<Arc<AppFiles>>) -> impl Filter<Extract = (Option<Arc<AppFiles>>, ), Error = Infallible> + Clone {
+    warp::any().map(move || app_files.clone())
+}
+
+/// Extracts who presents the token in the `Authorization: Bearer <token>` header, rejecting
+/// the request if it is not valid. Nobody is extracted if authentication is disabled.
+fn with_auth(context: AppContextRef) -> impl Filter<Extract = (Option<AuthIdentity>, ), Error = Rejection> + Clone {
+    warp::header::optional("authorization").and(with_context(context)).and_then(handler::authenticate)
+}
+
+/// Lets through the requests of paired browsers, or those that present the pairing secret,
+/// extracting the session cookie to set in the latter case. Everything is let through if
+/// pairing is disabled.
+fn with_pairing(context: AppContextRef) -> impl Filter<Extract = (Option<String>, ), Error = Rejection> + Clone {
+    warp::cookie::optional(PAIRING_COOKIE).and(warp::query()).and(with_context(context)).and_then(handler::check_pairing)
+}
+
+/// Rejects the requests of web pages whose origin is not allowed, before the CORS policy
+/// and the websocket upgrades see them.
+fn with_origin(allowed_origins: Arc<AllowedOrigins>) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional("origin").and(warp::any().map(move || allowed_origins.clone())).and_then(handler::check_origin).untuple_one()
+}
+
 fn with_context(context: AppContextRef) -> impl Filter<Extract = (AppContextRef, ), Error = Infallible> + Clone {
     warp::any().map(move || context.clone())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    // `tokio` exports its own `test` macro.
+    use std::prelude::v1::test;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use tokio::sync::Mutex;
+    use warp::http::StatusCode;
+
+    use crate::application::AppContext;
+    use crate::auth::AppAuth;
+    use crate::auth::credentials::CredentialStore;
+    use crate::network::ws::methods::WsMethodRegistry;
+
+    use super::*;
+
+    /// The routes of a core that requires authentication and serves the app in `app_dir`.
+    fn app_routes(app_dir: &str) -> impl Filter<Extract = impl Reply, Error = Infallible> + Clone {
+        let dir = std::env::temp_dir().join(app_dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("index.html"), "app").unwrap();
+
+        let mut context = AppContext::new(WsMethodRegistry::with_defaults());
+        context.auth = Some(AppAuth::new(CredentialStore::load(PathBuf::from("none.json")).unwrap(), Duration::from_secs(60)));
+        let app_files = Some(Arc::new(AppFiles::Directory(dir)));
+        routes(Arc::new(Mutex::new(context)), app_files, AllowedOrigins::Any)
+    }
+
+    #[tokio::test]
+    async fn test_app_does_not_hide_api_rejections() {
+        let routes = app_routes("core-test-api-rejections");
+        for path in &["/metrics", "/ws/schema"] {
+            let response = warp::test::request().path(path).reply(&routes).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{}", path);
+        }
+
+        let response = warp::test::request().path("/ws?token=bad").reply(&routes).await;
+        assert_ne!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_app_does_not_serve_api_paths() {
+        let routes = app_routes("core-test-api-paths");
+        for path in &["/ws/unknown", "/auth/unknown", "/metrics/unknown", "/version/unknown"] {
+            let response = warp::test::request().path(path).reply(&routes).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", path);
+        }
+
+        let response = warp::test::request().path("/projects/1").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body().as_ref(), b"app");
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::BTreeSet;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use warp::filters::cors::Builder;
+use warp::http::Uri;
+
+/// The origins of the web pages that can talk to the core, checked against the `Origin`
+/// header that browsers send. Requests without that header do not come from a web page.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AllowedOrigins {
+    // Any web page.
+    Any,
+    // Only the pages of these origins, like `http://localhost:8080`.
+    List(BTreeSet<String>),
+}
+
+impl AllowedOrigins {
+    /// The origins of the app served by the core at `addr`. When it listens on every
+    /// interface, those are the origins of the loopback interface, because browsers never
+    /// send the unspecified address.
+    pub fn for_address(addr: &SocketAddr) -> AllowedOrigins {
+        let mut origins = BTreeSet::new();
+
+        if addr.ip().is_unspecified() {
+            origins.insert(format!("http://127.0.0.1:{}", addr.port()));
+            origins.insert(format!("http://[::1]:{}", addr.port()));
+        } else {
+            origins.insert(format!("http://{}", addr));
+        }
+
+        if addr.ip().is_loopback() || addr.ip().is_unspecified() {
+            origins.insert(format!("http://localhost:{}", addr.port()));
+        }
+
+        AllowedOrigins::List(origins)
+    }
+
+    pub fn allows(&self, origin: &str) -> bool {
+        match self {
+            AllowedOrigins::Any => true,
+            AllowedOrigins::List(origins) => origins.contains(&origin.to_ascii_lowercase()),
+        }
+    }
+
+    /// The CORS policy that lets the allowed origins call the API.
+    pub fn cors(&self) -> Builder {
+        let cors = warp::cors()
+            .allow_methods(vec!["GET", "POST"])
+            .allow_headers(vec!["authorization", "content-type", "if-none-match"]);
+
+        // Only the listed origins can send the cookies of the browser.
+        match self {
+            AllowedOrigins::Any => cors.allow_any_origin(),
+            AllowedOrigins::List(origins) => cors.allow_origins(origins.iter().map(String::as_str)).allow_credentials(true),
+        }
+    }
+}
+
+impl FromStr for AllowedOrigins {
+    type Err = String;
+
+    /// Parses `*`, or a list like `<scheme>://<host>[:<port>],...`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim() == "*" {
+            return Ok(AllowedOrigins::Any);
+        }
+
+        let origins = s.split(',').map(|v| v.trim()).filter(|v| !v.is_empty()).map(|v| parse_origin(v).ok_or_else(|| format!("Invalid origin: {}", v))).collect::<Result<_, _>>()?;
+        Ok(AllowedOrigins::List(origins))
+    }
+}
+
+/// Normalizes an origin, which is a URL without path, query or credentials.
+fn parse_origin(s: &str) -> Option<String> {
+    let uri: Uri = s.parse().ok()?;
+    let scheme = uri.scheme_str()?;
+    let authority = uri.authority()?;
+
+    if authority.as_str().contains('@') || !matches!(uri.path(), "" | "/") || uri.query().is_some() {
+        return None;
+    }
+
+    Some(format!("{}://{}", scheme, authority).to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    // `tokio` exports its own `test` macro.
+    use std::prelude::v1::test;
+
+    use super::*;
+
+    fn origins(addr: &str) -> AllowedOrigins {
+        AllowedOrigins::for_address(&addr.parse().unwrap())
+    }
+
+    #[test]
+    fn test_loopback_address() {
+        let origins = origins("127.0.0.1:21012");
+        assert!(origins.allows("http://127.0.0.1:21012"));
+        assert!(origins.allows("http://localhost:21012"));
+        assert!(!origins.allows("http://localhost:8080"));
+        assert!(!origins.allows("https://example.com"));
+    }
+
+    #[test]
+    fn test_unspecified_address() {
+        for addr in &["0.0.0.0:21012", "[::]:21012"] {
+            let origins = origins(addr);
+            assert!(origins.allows("http://127.0.0.1:21012"));
+            assert!(origins.allows("http://[::1]:21012"));
+            assert!(origins.allows("http://localhost:21012"));
+            assert!(!origins.allows("http://0.0.0.0:21012"));
+        }
+    }
+
+    #[test]
+    fn test_other_address() {
+        let origins = origins("192.168.1.10:21012");
+        assert!(origins.allows("http://192.168.1.10:21012"));
+        assert!(!origins.allows("http://localhost:21012"));
+    }
+
+    #[test]
+    fn test_parse_any() {
+        assert_eq!(" * ".parse(), Ok(AllowedOrigins::Any));
+        assert!(AllowedOrigins::Any.allows("https://example.com"));
+    }
+
+    #[test]
+    fn test_parse_list() {
+        let origins: AllowedOrigins = "http://localhost:8080, HTTPS://Example.com/,".parse().unwrap();
+        assert_eq!(origins, AllowedOrigins::List(vec!["http://localhost:8080".to_string(), "https://example.com".to_string()].into_iter().collect()));
+        assert!(origins.allows("https://EXAMPLE.com"));
+        assert!(!origins.allows("http://example.com"));
+    }
+
+    #[test]
+    fn test_parse_invalid_origins() {
+        for s in &["localhost:8080", "http://", "http://example.com/path", "http://example.com?a=1", "http://user@example.com"] {
+            assert!(s.parse::<AllowedOrigins>().is_err(), "{} should be invalid", s);
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use serde_json::Value;
+
+use crate::network::ws::messages::jsonrpc::{from_jsonrpc, to_jsonrpc};
+use crate::network::ws::messages::{WsError, WsErrorId, WsMessage};
+
+/// The format of the messages exchanged with a client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WsCodec {
+    // The format described in `docs/ws.md`.
+    Native,
+    // JSON-RPC 2.0.
+    JsonRpc,
+}
+
+/// The content of a frame: either a single message or a batch of them.
+pub enum WsFrame {
+    Single(WsMessage),
+    Batch(Vec<WsMessage>),
+}
+
+impl WsFrame {
+    pub fn is_notification(&self) -> bool {
+        matches!(self, WsFrame::Single(WsMessage::Notification(_)))
+    }
+}
+
+impl WsCodec {
+    pub fn write_frame(&self, frame: &WsFrame) -> Result<Value, serde_json::Error> {
+        match (self, frame) {
+            (WsCodec::Native, WsFrame::Single(message)) => serde_json::to_value(message),
+            (WsCodec::Native, WsFrame::Batch(messages)) => serde_json::to_value(messages),
+            (WsCodec::JsonRpc, WsFrame::Single(message)) => to_jsonrpc(message),
+            (WsCodec::JsonRpc, WsFrame::Batch(messages)) => messages.iter().map(to_jsonrpc).collect::<Result<Vec<Value>, _>>().map(Value::Array),
+        }
+    }
+
+    /// Reads a frame, or returns the error to answer it with if it must be answered. Batches
+    /// are sent as arrays of messages. The elements of JSON-RPC batches are read one by one,
+    /// returning the errors to answer the invalid ones with next to the frame.
+    pub fn read_frame(&self, value: Value) -> Result<(WsFrame, Vec<WsError>), Option<WsError>> {
+        let incorrect_input = |e: serde_json::Error| Some(WsError::new_no_id_string(WsErrorId::IncorrectInput, e.to_string()));
+        match (self, value) {
+            (WsCodec::Native, value @ Value::Array(_)) => serde_json::from_value(value).map(|v| (WsFrame::Batch(v), Vec::new())).map_err(incorrect_input),
+            (WsCodec::Native, value) => serde_json::from_value(value).map(|v| (WsFrame::Single(v), Vec::new())).map_err(incorrect_input),
+            (WsCodec::JsonRpc, Value::Array(values)) => {
+                let mut messages = Vec::new();
+                let mut errors = Vec::new();
+                for value in values {
+                    match from_jsonrpc(value) {
+                        Ok(message) => messages.push(message),
+                        Err(Some(error)) => errors.push(error),
+                        Err(None) => {}
+                    }
+                }
+
+                Ok((WsFrame::Batch(messages), errors))
+            }
+            (WsCodec::JsonRpc, value) => from_jsonrpc(value).map(|v| (WsFrame::Single(v), Vec::new())),
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::{AbortHandle, Abortable, AbortRegistration, join, join_all};
+use tokio::sync::{mpsc, Semaphore};
+
+use crate::application::{AppContext, AppContextRef};
+use crate::network::ws::messages::{WsError, WsErrorId, WsMessage, WsRequest, WsResponse};
+use crate::network::ws::methods::WsMethodContext;
+use crate::network::ws::middleware::WsRequestContext;
+use crate::network::ws::outbox::wait_outbox_flushed;
+use crate::network::ws::{send_ws_message, send_ws_messages};
+
+// The time the server waits for the answer to a shutdown request to be sent.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Runs the requests of a connection in their own tasks, limiting how many of them
+/// are processed at the same time. Requests to sequential methods are processed one
+/// after another in the order they were received. Requests wait for their turn in
+/// their tasks so that the connection keeps being read, but a client cannot have more
+/// than `max_pending_requests` of them.
+///
+/// Every request can be cancelled through the abort handle stored in the
+/// `running_requests` of its client until it finishes.
+pub struct WsRequestDispatcher {
+    connection_id: Arc<String>,
+    context_ref: AppContextRef,
+    permits: Arc<Semaphore>,
+    sequential_sender: mpsc::UnboundedSender<QueuedRequest>,
+}
+
+type QueuedRequest = (WsRequest, AbortRegistration);
+
+impl WsRequestDispatcher {
+    pub fn new(connection_id: Arc<String>, context_ref: AppContextRef, max_concurrent_requests: usize) -> WsRequestDispatcher {
+        let (sequential_sender, mut sequential_rcv) = mpsc::unbounded_channel::<QueuedRequest>();
+        let permits = Arc::new(Semaphore::new(max_concurrent_requests));
+
+        // Process sequential requests in order.
+        {
+            let connection_id = connection_id.clone();
+            let context_ref = context_ref.clone();
+            let permits = permits.clone();
+            tokio::spawn(async move {
+                while let Some((request, abort_registration)) = sequential_rcv.recv().await {
+                    respond_request(&connection_id, request, abort_registration, &permits, &context_ref).await;
+                }
+            });
+        }
+
+        WsRequestDispatcher {
+            connection_id,
+            context_ref,
+            permits,
+            sequential_sender,
+        }
+    }
+
+    /// Starts processing the request, which waits for its turn in its own task.
+    pub async fn dispatch(&self, request: WsRequest) {
+        let (abort_registration, sequential) = match self.register(&request).await {
+            Ok(v) => v,
+            Err(error) => {
+                if let Err(e) = send_ws_message(&self.connection_id, WsMessage::Error(error), &self.context_ref).await {
+                    error!("Error while sending response: {}", e)
+                }
+
+                return;
+            }
+        };
+
+        if sequential {
+            if let Err(e) = self.sequential_sender.send((request, abort_registration)) {
+                error!("Error while queueing sequential request: {}", e);
+            }
+        } else {
+            let connection_id = self.connection_id.clone();
+            let context_ref = self.context_ref.clone();
+            let permits = self.permits.clone();
+            tokio::spawn(async move {
+                respond_request(&connection_id, request, abort_registration, &permits, &context_ref).await;
+            });
+        }
+    }
+
+    /// Processes a batch of requests answering them all at once, in the same order,
+    /// when all of them have finished, followed by the `errors` of the messages of the
+    /// batch that could not be read. Requests to sequential methods are processed one
+    /// after another in the order they appear in the batch.
+    pub async fn dispatch_batch(&self, requests: Vec<WsRequest>, errors: Vec<WsError>) {
+        let mut rejected: Vec<_> = errors.into_iter().enumerate().map(|(index, error)| (requests.len() + index, WsMessage::Error(error))).collect();
+        let mut concurrent = Vec::new();
+        let mut sequential = Vec::new();
+        for (index, request) in requests.into_iter().enumerate() {
+            match self.register(&request).await {
+                Ok((abort_registration, true)) => sequential.push((index, request, abort_registration)),
+                Ok((abort_registration, false)) => concurrent.push((index, request, abort_registration)),
+                Err(error) => rejected.push((index, WsMessage::Error(error))),
+            }
+        }
+
+        let connection_id = self.connection_id.clone();
+        let context_ref = self.context_ref.clone();
+        let permits = self.permits.clone();
+        tokio::spawn(async move {
+            let sequential_future = async {
+                let mut messages = Vec::with_capacity(sequential.len());
+                for (index, request, abort_registration) in sequential {
+                    messages.push((index, run_request(&connection_id, request, abort_registration, &permits, &context_ref).await));
+                }
+
+                messages
+            };
+
+            let concurrent_future = join_all(concurrent.into_iter().map(|(index, request, abort_registration)| {
+                let connection_id = &connection_id;
+                let context_ref = &context_ref;
+                let permits = &permits;
+                async move { (index, run_request(connection_id, request, abort_registration, permits, context_ref).await) }
+            }));
+
+            let (mut messages, concurrent_messages) = join(sequential_future, concurrent_future).await;
+            messages.extend(concurrent_messages);
+            messages.extend(rejected);
+            messages.sort_by_key(|(index, _)| *index);
+
+            let messages: Vec<WsMessage> = messages.into_iter().map(|(_, message)| message).collect();
+            let ids: Vec<Arc<String>> = messages.iter().filter_map(answered_request_id).collect();
+            if let Err(e) = send_ws_messages(&connection_id, messages, &context_ref).await {
+                error!("Error while sending batch response: {}", e)
+            }
+
+            shutdown_if_requested(&connection_id, &ids, &context_ref).await;
+        });
+    }
+
+    /// Makes the request cancellable returning whether it must be processed sequentially,
+    /// a `Conflict` error if the client has another pending request with its id, or a
+    /// `RateLimited` error if the client has too many pending requests.
+    async fn register(&self, request: &WsRequest) -> Result<(AbortRegistration, bool), WsError> {
+        let (abort_handle, abort_registration) = AbortHandle::new_pair();
+        let mut context = self.context_ref.lock().await;
+        let max_pending_requests = context.max_pending_requests;
+        if let Some(client) = context.client_mut(&self.connection_id) {
+            if client.running_requests.contains_key(&request.id) {
+                warn!("Client {} reused the id of the pending request {}", self.connection_id, request.id);
+                return Err(WsError::new_string(request.id.clone(), WsErrorId::Conflict, format!("Request {} is already pending", request.id)));
+            }
+
+            if client.running_requests.len() >= max_pending_requests {
+                warn!("Client {} has too many pending requests", self.connection_id);
+                return Err(WsError::new_string(request.id.clone(), WsErrorId::RateLimited, "Too many pending requests".to_string()));
+            }
+
+            client.running_requests.insert(request.id.clone(), abort_handle);
+        }
+
+        Ok((abort_registration, context.methods.is_sequential(&request.method)))
+    }
+}
+
+/// Processes a request and sends the result back to the client.
+async fn respond_request(connection_id: &Arc<String>, request: WsRequest, abort_registration: AbortRegistration, permits: &Arc<Semaphore>, context_ref: &AppContextRef) {
+    let id = request.id.clone();
+    let message = run_request(connection_id, request, abort_registration, permits, context_ref).await;
+
+    if let Err(e) = send_ws_message(connection_id, message, context_ref).await {
+        error!("Error while sending response: {}", e)
+    }
+
+    shutdown_if_requested(connection_id, &[id], context_ref).await;
+}
+
+fn answered_request_id(message: &WsMessage) -> Option<Arc<String>> {
+    match message {
+        WsMessage::Response(response) => Some(response.id.clone()),
+        WsMessage::Error(error) => error.id.clone(),
+        _ => None,
+    }
+}
+
+/// Closes the application if one of the answered requests asked for it, once the answers
+/// have been written in the websocket of the client.
+async fn shutdown_if_requested(connection_id: &Arc<String>, ids: &[Arc<String>], context_ref: &AppContextRef) {
+    let requested = match &context_ref.lock().await.shutdown_request {
+        Some((shutdown_connection_id, shutdown_id)) => shutdown_connection_id == connection_id && ids.contains(shutdown_id),
+        None => false,
+    };
+
+    if !requested {
+        return;
+    }
+
+    if tokio::time::timeout(SHUTDOWN_TIMEOUT, wait_outbox_flushed(connection_id, context_ref)).await.is_err() {
+        debug!("Cannot send the answer to the shutdown request to client {}", connection_id);
+    }
+
+    context_ref.lock().await.shutdown();
+}
+
+/// Processes a request, once there is a permit for it, returning the message to answer it
+/// with, or a `Cancelled` error if it is aborted before finishing. The middlewares run
+/// around it, and requests the client is not allowed to make get an `Unauthorized` error instead.
+async fn run_request(connection_id: &Arc<String>, request: WsRequest, abort_registration: AbortRegistration, permits: &Arc<Semaphore>, context_ref: &AppContextRef) -> WsMessage {
+    let id = request.id.clone();
+    let mut request_context = WsRequestContext::new();
+    let (middlewares, (entered, accepted)) = {
+        let mut context = context_ref.lock().await;
+        let middlewares = context.middlewares.clone();
+        let accepted = match context.client_mut(connection_id) {
+            Some(client) => middlewares.before(connection_id, client, &request, &mut request_context),
+            None => (0, Ok(())),
+        };
+
+        let accepted = match accepted {
+            (entered, Ok(_)) => (entered, authorize(connection_id, &request, &context)),
+            accepted => accepted,
+        };
+
+        (middlewares, accepted)
+    };
+
+    let mut message = match accepted {
+        Ok(_) => {
+            let process = async {
+                let _permit = permits.clone().acquire_owned().await;
+                process_request(connection_id, request.clone(), context_ref).await
+            };
+
+            match Abortable::new(process, abort_registration).await {
+                Ok(message) => message,
+                Err(_) => {
+                    trace!("Request {} cancelled", id);
+                    WsMessage::Error(WsError::new_string(id.clone(), WsErrorId::Cancelled, "Request cancelled".to_string()))
+                }
+            }
+        }
+        Err(error) => WsMessage::Error(error),
+    };
+
+    let mut context = context_ref.lock().await;
+    if let Some(client) = context.client_mut(connection_id) {
+        client.running_requests.remove(&id);
+    }
+
+    middlewares.after(entered, connection_id, context.client(connection_id), &request, &mut request_context, &mut message);
+
+    message
+}
+
+fn authorize(connection_id: &Arc<String>, request: &WsRequest, context: &AppContext) -> Result<(), WsError> {
+    if context.client(connection_id).is_none_or(|client| context.is_authorized(client, &request.method)) {
+        return Ok(());
+    }
+
+    warn!("Client {} is not allowed to call {}", connection_id, request.method);
+    Err(WsError::new_string(request.id.clone(), WsErrorId::Unauthorized, format!("Not allowed to call {}", request.method)))
+}
+
+/// Processes a request returning the message to answer it with.
+pub async fn process_request(connection_id: &Arc<String>, request: WsRequest, context_ref: &AppContextRef) -> WsMessage {
+    let WsRequest { id, method, params } = request;
+    let methods = context_ref.lock().await.methods.clone();
+    let method_context = WsMethodContext {
+        request_id: id.clone(),
+        connection_id: connection_id.clone(),
+        context_ref: context_ref.clone(),
+    };
+
+    match methods.dispatch(method_context, &method, params).await {
+        Ok(result) => WsMessage::Response(WsResponse::response_from(id, result)),
+        Err(e) => WsMessage::Error(e)
+    }
+}
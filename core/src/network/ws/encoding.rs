@@ -0,0 +1,51 @@
+use serde::Deserialize;
+use serde_json::Value;
+use warp::ws::Message;
+
+/// How the messages exchanged with a client are serialized.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum WsEncoding {
+    // Text frames with JSON.
+    #[default]
+    Json,
+    // Binary frames with MessagePack.
+    #[serde(rename = "msgpack")]
+    MessagePack,
+    // Binary frames with CBOR.
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+impl WsEncoding {
+    pub fn encode(&self, value: &Value) -> Result<Message, String> {
+        match self {
+            WsEncoding::Json => serde_json::to_string(value).map(Message::text).map_err(|e| e.to_string()),
+            WsEncoding::MessagePack => rmp_serde::to_vec_named(value).map(Message::binary).map_err(|e| e.to_string()),
+            #[cfg(feature = "cbor")]
+            WsEncoding::Cbor => serde_cbor::to_vec(value).map(Message::binary).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Decodes the content of a frame. Text frames are always read as JSON, while binary
+    /// ones are read with the encoding of the connection, or MessagePack if it uses JSON.
+    /// Control frames have no content so they return `None`.
+    pub fn decode(&self, msg: &Message) -> Option<Result<Value, String>> {
+        if let Ok(text) = msg.to_str() {
+            return Some(serde_json::from_str(text).map_err(|e| e.to_string()));
+        }
+
+        if !msg.is_binary() {
+            return None;
+        }
+
+        let bytes = msg.as_bytes();
+        let result = match self {
+            WsEncoding::Json | WsEncoding::MessagePack => rmp_serde::from_slice(bytes).map_err(|e| e.to_string()),
+            #[cfg(feature = "cbor")]
+            WsEncoding::Cbor => serde_cbor::from_slice(bytes).map_err(|e| e.to_string()),
+        };
+
+        Some(result)
+    }
+}
@@ -1,7 +1,8 @@
 use std::error::Error;
 use std::fmt::Display;
+use std::fmt::Formatter;
 
-use serde::export::Formatter;
+use crate::network::ws::messages::{ToWsError, WsErrorId};
 
 #[derive(Debug)]
 pub struct ClientDisconnectedError {}
@@ -12,4 +13,46 @@ impl Display for ClientDisconnectedError {
     }
 }
 
-impl Error for ClientDisconnectedError {}
\ No newline at end of file
+impl Error for ClientDisconnectedError {}
+
+impl ToWsError for ClientDisconnectedError {
+    fn error_id(&self) -> WsErrorId {
+        WsErrorId::Disconnected
+    }
+}
+
+#[derive(Debug)]
+pub struct EncodingError {
+    pub message: String,
+}
+
+impl Display for EncodingError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Cannot encode message: {}", self.message)
+    }
+}
+
+impl Error for EncodingError {}
+
+impl ToWsError for EncodingError {
+    fn error_id(&self) -> WsErrorId {
+        WsErrorId::Internal
+    }
+}
+
+#[derive(Debug)]
+pub struct OutboxFullError {}
+
+impl Display for OutboxFullError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The outbound queue of the client is full")
+    }
+}
+
+impl Error for OutboxFullError {}
+
+impl ToWsError for OutboxFullError {
+    fn error_id(&self) -> WsErrorId {
+        WsErrorId::Internal
+    }
+}
@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use futures::future::AbortHandle;
+use warp::ws::Message;
+
+use crate::application::AppContextRef;
+use crate::network::ws::close_ws_connection;
+use crate::network::ws::messages::WsCloseReason;
+
+/// Pings the client with `connection_id` periodically and, if it does not send anything
+/// within the idle timeout, closes its connection and stops listening to it through
+/// `connection_abort`, so that half-open connections do not keep their slot forever.
+/// Clients that are not authenticated when a ping is due are disconnected too. It stops when the
+/// connection identified by `socket_id` is replaced.
+pub async fn run_heartbeat(connection_id: Arc<String>, socket_id: Arc<String>, context_ref: AppContextRef, connection_abort: AbortHandle) {
+    loop {
+        let ping_interval = context_ref.lock().await.ping_interval;
+        tokio::time::delay_for(ping_interval).await;
+
+        let is_idle = {
+            let mut context = context_ref.lock().await;
+            let idle_timeout = context.idle_timeout;
+            let is_authenticated = match context.client(&connection_id) {
+                Some(v) if v.socket_id == socket_id => context.is_authenticated(v),
+                _ => return,
+            };
+
+            let client = context.client_mut(&connection_id).unwrap();
+
+            // Clients have until the first ping to authenticate, and their tokens can expire.
+            if !is_authenticated {
+                warn!("Client {} is not authenticated, disconnecting it", connection_id);
+                client.close(WsCloseReason::Unauthenticated);
+                return;
+            }
+
+            if client.is_idle(idle_timeout) {
+                true
+            } else {
+                client.outbox.push_priority(Message::ping(Vec::new()));
+                false
+            }
+        };
+
+        if is_idle {
+            warn!("Client {} is idle, disconnecting it", connection_id);
+
+            let _ = close_ws_connection(&connection_id, WsCloseReason::IdleTimeout, &context_ref).await;
+            connection_abort.abort();
+            return;
+        }
+    }
+}
@@ -0,0 +1,390 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+use crate::network::ws::messages::{WsError, WsErrorId, WsMessage, WsNotification, WsRequest, WsResponse, WsResponseResult};
+
+const JSON_RPC_VERSION: &str = "2.0";
+
+/// The method of the notifications that carry `WsProgress` messages.
+const PROGRESS_METHOD: &str = "progress";
+
+/// The method of the notifications that cancel a request by its id.
+const CANCEL_METHOD: &str = "cancel";
+
+/// A JSON-RPC 2.0 message. Which fields are present tells its kind.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct JsonRpcMessage {
+    jsonrpc: String,
+
+    #[serde(default, deserialize_with = "present")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<Value>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    method: Option<String>,
+
+    #[serde(default, deserialize_with = "present")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    params: Option<Value>,
+
+    #[serde(default, deserialize_with = "present")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<JsonRpcError>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct JsonRpcError {
+    code: i64,
+    message: String,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+}
+
+impl JsonRpcMessage {
+    fn new(id: Option<Value>) -> JsonRpcMessage {
+        JsonRpcMessage {
+            jsonrpc: JSON_RPC_VERSION.to_string(),
+            id,
+            method: None,
+            params: None,
+            result: None,
+            error: None,
+        }
+    }
+}
+
+/// Distinguishes a field set to `null` from a missing one.
+fn present<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+    Value::deserialize(deserializer).map(Some)
+}
+
+// ----------------------------------------------------------------------------
+// ----------------------------------------------------------------------------
+// ----------------------------------------------------------------------------
+
+/// Converts a message into its JSON-RPC 2.0 representation.
+pub fn to_jsonrpc(message: &WsMessage) -> Result<Value, serde_json::Error> {
+    let jsonrpc_message = match message {
+        WsMessage::Request(request) => {
+            let mut result = JsonRpcMessage::new(Some(to_jsonrpc_id(&request.id)));
+            result.method = Some(request.method.to_string());
+            result.params = request.params.clone();
+            result
+        }
+        WsMessage::Notification(notification) => {
+            // Reuse the `method` and `params` fields of the native format.
+            let mut native = match serde_json::to_value(notification)? {
+                Value::Object(v) => v,
+                _ => unreachable!("Notifications are serialized as objects"),
+            };
+
+            let mut result = JsonRpcMessage::new(None);
+            result.method = native.remove("method").and_then(|v| v.as_str().map(|v| v.to_string()));
+            result.params = native.remove("params");
+            result
+        }
+        WsMessage::Response(response) => {
+            let mut result = JsonRpcMessage::new(Some(to_jsonrpc_id(&response.id)));
+            result.result = Some(serde_json::to_value(&response.result)?);
+            result
+        }
+        WsMessage::Error(error) => {
+            let mut result = JsonRpcMessage::new(Some(error.id.as_ref().map_or(Value::Null, |id| to_jsonrpc_id(id))));
+            result.error = Some(JsonRpcError {
+                code: error_code(error.eid),
+                message: error.message.to_string(),
+                data: error.data.clone(),
+            });
+            result
+        }
+        WsMessage::Progress(progress) => {
+            let mut params = serde_json::to_value(progress)?;
+            params["id"] = to_jsonrpc_id(&progress.id);
+
+            let mut result = JsonRpcMessage::new(None);
+            result.method = Some(PROGRESS_METHOD.to_string());
+            result.params = Some(params);
+            result
+        }
+    };
+
+    serde_json::to_value(jsonrpc_message)
+}
+
+/// Reads a message from its JSON-RPC 2.0 representation, or returns the error to answer
+/// it with. Invalid notifications are not answered, so they have no error.
+pub fn from_jsonrpc(value: Value) -> Result<WsMessage, Option<WsError>> {
+    let is_notification = value.get("method").is_some() && value.get("id").is_none();
+    let id = value.get("id").filter(|v| v.is_number() || v.is_string()).map(from_jsonrpc_request_id);
+
+    read_jsonrpc(value).map_err(|e| {
+        if is_notification {
+            return None;
+        }
+
+        let error = WsError::new_no_id_string(WsErrorId::IncorrectInput, e);
+        Some(match id {
+            Some(id) => error.with_id(id),
+            None => error,
+        })
+    })
+}
+
+fn read_jsonrpc(value: Value) -> Result<WsMessage, String> {
+    let message: JsonRpcMessage = serde_json::from_value(value).map_err(|e| e.to_string())?;
+    if message.jsonrpc != JSON_RPC_VERSION {
+        return Err(format!("Unsupported JSON-RPC version: {}", message.jsonrpc));
+    }
+
+    match message {
+        JsonRpcMessage { method: Some(method), id: Some(id), result: None, error: None, params, .. } => {
+            Ok(WsMessage::Request(WsRequest::new(from_jsonrpc_request_id(&id), Arc::new(method), params)))
+        }
+        JsonRpcMessage { method: Some(method), id: None, result: None, error: None, mut params, .. } => {
+            // The client cancels its own requests, whose ids keep their JSON representation.
+            if method == CANCEL_METHOD {
+                if let Some(id) = params.as_mut().and_then(|v| v.get_mut("id")) {
+                    *id = Value::String(from_jsonrpc_request_id(id).to_string());
+                }
+            }
+
+            let mut native = serde_json::Map::new();
+            native.insert("method".to_string(), Value::String(method));
+            if let Some(params) = params {
+                native.insert("params".to_string(), params);
+            }
+
+            let notification: WsNotification = serde_json::from_value(Value::Object(native)).map_err(|e| e.to_string())?;
+            Ok(WsMessage::Notification(notification))
+        }
+        JsonRpcMessage { method: None, id: Some(id), result: Some(result), error: None, params: None, .. } => {
+            let result: WsResponseResult = serde_json::from_value(result).map_err(|e| e.to_string())?;
+            Ok(WsMessage::Response(WsResponse::response_from(from_jsonrpc_response_id(&id), result)))
+        }
+        JsonRpcMessage { method: None, id, result: None, error: Some(error), params: None, .. } => {
+            let id = id.filter(|v| !v.is_null()).map(|v| from_jsonrpc_response_id(&v));
+            Ok(WsMessage::Error(WsError {
+                id,
+                eid: error_id(error.code),
+                message: Arc::new(error.message),
+                data: error.data,
+            }))
+        }
+        _ => Err("Invalid JSON-RPC message".to_string())
+    }
+}
+
+// ----------------------------------------------------------------------------
+// ----------------------------------------------------------------------------
+// ----------------------------------------------------------------------------
+
+// The ids of the requests of the client keep their JSON representation, so that
+// numeric and string ids are sent back with their original type.
+fn from_jsonrpc_request_id(id: &Value) -> Arc<String> {
+    Arc::new(id.to_string())
+}
+
+// The ids of the responses of the client are those the core sent in its requests.
+fn from_jsonrpc_response_id(id: &Value) -> Arc<String> {
+    match id {
+        Value::String(id) => Arc::new(id.clone()),
+        id => Arc::new(id.to_string()),
+    }
+}
+
+fn to_jsonrpc_id(id: &str) -> Value {
+    match serde_json::from_str(id) {
+        Ok(v @ Value::Number(_)) | Ok(v @ Value::String(_)) | Ok(v @ Value::Null) => v,
+        _ => Value::String(id.to_string()),
+    }
+}
+
+fn error_code(eid: WsErrorId) -> i64 {
+    match eid {
+        WsErrorId::ParseError => -32700,
+        WsErrorId::IncorrectInput => -32600,
+        WsErrorId::MethodNotFound => -32601,
+        WsErrorId::InvalidParams => -32602,
+        WsErrorId::Internal => -32603,
+        WsErrorId::Unauthorized => -32001,
+        WsErrorId::Timeout => -32002,
+        WsErrorId::Cancelled => -32003,
+        WsErrorId::Disconnected => -32004,
+        WsErrorId::NotFound => -32005,
+        WsErrorId::Conflict => -32006,
+        WsErrorId::RateLimited => -32007,
+    }
+}
+
+fn error_id(code: i64) -> WsErrorId {
+    match code {
+        -32700 => WsErrorId::ParseError,
+        -32600 => WsErrorId::IncorrectInput,
+        -32601 => WsErrorId::MethodNotFound,
+        -32602 => WsErrorId::InvalidParams,
+        -32001 => WsErrorId::Unauthorized,
+        -32002 => WsErrorId::Timeout,
+        -32003 => WsErrorId::Cancelled,
+        -32004 => WsErrorId::Disconnected,
+        -32005 => WsErrorId::NotFound,
+        -32006 => WsErrorId::Conflict,
+        -32007 => WsErrorId::RateLimited,
+        _ => WsErrorId::Internal,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // `tokio` exports its own `test` macro.
+    use std::prelude::v1::test;
+
+    use serde_json::json;
+
+    use crate::network::ws::codec::{WsCodec, WsFrame};
+    use crate::network::ws::messages::{WsNotificationMethod, WsProgress};
+    use crate::network::ws::methods::read_params;
+
+    use super::*;
+
+    fn client_ids() -> Vec<Value> {
+        vec![json!(7), json!("abc")]
+    }
+
+    fn request_id(id: &Value) -> Arc<String> {
+        let request = json!({ "jsonrpc": "2.0", "id": id, "method": "ping" });
+        match from_jsonrpc(request).unwrap() {
+            WsMessage::Request(request) => request.id,
+            message => panic!("Expected a request: {:?}", message),
+        }
+    }
+
+    #[test]
+    fn test_response_keeps_request_id() {
+        for id in client_ids() {
+            let response = WsMessage::Response(WsResponse::response_from(request_id(&id), WsResponseResult::pong()));
+            assert_eq!(to_jsonrpc(&response).unwrap(), json!({ "jsonrpc": "2.0", "id": id, "result": "pong" }));
+        }
+    }
+
+    #[test]
+    fn test_error_keeps_request_id() {
+        for id in client_ids() {
+            let error = WsMessage::Error(WsError::new_string(request_id(&id), WsErrorId::MethodNotFound, "Method not found".to_string()));
+            let value = to_jsonrpc(&error).unwrap();
+            assert_eq!(value["id"], id);
+            assert_eq!(value["error"]["code"], json!(-32601));
+        }
+    }
+
+    #[test]
+    fn test_progress_keeps_request_id() {
+        for id in client_ids() {
+            let progress = WsMessage::Progress(WsProgress::progress(request_id(&id), 0.5, None));
+            let value = to_jsonrpc(&progress).unwrap();
+            assert_eq!(value["method"], json!(PROGRESS_METHOD));
+            assert_eq!(value["params"]["id"], id);
+        }
+    }
+
+    #[test]
+    fn test_cancel_matches_request_id() {
+        for id in client_ids() {
+            let cancel = json!({ "jsonrpc": "2.0", "method": "cancel", "params": { "id": id } });
+            match from_jsonrpc(cancel).unwrap() {
+                WsMessage::Notification(WsNotification { method: WsNotificationMethod::Cancel(params), .. }) => assert_eq!(params.id, request_id(&id)),
+                message => panic!("Expected a cancel notification: {:?}", message),
+            }
+        }
+    }
+
+    #[test]
+    fn test_server_request_round_trip() {
+        let id = Arc::new("f2a4c6e8".to_string());
+        let request = WsMessage::Request(WsRequest::new(id.clone(), Arc::new("echo".to_string()), Some(json!("hi"))));
+        assert_eq!(to_jsonrpc(&request).unwrap()["id"], json!("f2a4c6e8"));
+
+        let response = json!({ "jsonrpc": "2.0", "id": "f2a4c6e8", "result": "hi" });
+        match from_jsonrpc(response).unwrap() {
+            WsMessage::Response(response) => assert_eq!(response.id, id),
+            message => panic!("Expected a response: {:?}", message),
+        }
+
+        let error = json!({ "jsonrpc": "2.0", "id": "f2a4c6e8", "error": { "code": -32003, "message": "Cancelled" } });
+        match from_jsonrpc(error).unwrap() {
+            WsMessage::Error(error) => {
+                assert_eq!(error.id, Some(id));
+                assert_eq!(error.eid, WsErrorId::Cancelled);
+            }
+            message => panic!("Expected an error: {:?}", message),
+        }
+    }
+
+    #[test]
+    fn test_parse_error_code() {
+        let error = WsMessage::Error(WsError::new_no_id_string(WsErrorId::ParseError, "expected value".to_string()));
+        assert_eq!(to_jsonrpc(&error).unwrap(), json!({ "jsonrpc": "2.0", "id": null, "error": { "code": -32700, "message": "expected value" } }));
+        assert_eq!(error_id(-32700), WsErrorId::ParseError);
+    }
+
+    #[test]
+    fn test_invalid_notification_is_not_answered() {
+        let notification = json!({ "jsonrpc": "2.0", "method": "unknown", "params": [1] });
+        assert!(from_jsonrpc(notification).unwrap_err().is_none());
+    }
+
+    #[test]
+    fn test_invalid_request_is_answered() {
+        for id in client_ids() {
+            let request = json!({ "jsonrpc": "1.0", "id": id, "method": "ping" });
+            let error = from_jsonrpc(request).unwrap_err().unwrap();
+            assert_eq!(error.eid, WsErrorId::IncorrectInput);
+            assert_eq!(error.id, Some(request_id(&id)));
+        }
+
+        let error = from_jsonrpc(json!(1)).unwrap_err().unwrap();
+        assert_eq!(error.id, None);
+    }
+
+    #[test]
+    fn test_batch_elements_are_read_one_by_one() {
+        let batch = json!([
+            { "jsonrpc": "2.0", "id": 1, "method": "ping" },
+            1,
+            { "jsonrpc": "2.0", "method": "unknown" },
+            { "jsonrpc": "2.0", "id": 2, "method": "ping", "extra": true },
+        ]);
+
+        match WsCodec::JsonRpc.read_frame(batch) {
+            Ok((WsFrame::Batch(messages), errors)) => {
+                assert_eq!(messages.len(), 1);
+                assert_eq!(errors.iter().map(|v| v.id.clone()).collect::<Vec<_>>(), vec![None, Some(request_id(&json!(2)))]);
+            }
+            _ => panic!("Expected a batch"),
+        }
+    }
+
+    #[test]
+    fn test_positional_params() {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": "echo", "params": ["hi"] });
+        let params = match from_jsonrpc(request).unwrap() {
+            WsMessage::Request(request) => request.params,
+            message => panic!("Expected a request: {:?}", message),
+        };
+
+        assert_eq!(read_params::<Arc<String>>(params.clone()).unwrap().as_str(), "hi");
+        assert_eq!(read_params::<Vec<String>>(params).unwrap(), vec!["hi".to_string()]);
+        read_params::<()>(Some(json!([]))).unwrap();
+        assert!(read_params::<Arc<String>>(Some(json!(["a", "b"]))).is_err());
+    }
+}
@@ -1,10 +1,17 @@
+use std::error::Error;
+use std::fmt::Display;
 use std::sync::Arc;
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+pub mod jsonrpc;
 
 lazy_static! {
-    static ref PONG_STRING: Arc<String> = Arc::new("pong".to_string());
-    static ref OK_STRING: Arc<String> = Arc::new("ok".to_string());
+    pub static ref PONG_STRING: Arc<String> = Arc::new("pong".to_string());
+    pub static ref OK_STRING: Arc<String> = Arc::new("ok".to_string());
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -23,25 +30,39 @@ pub enum WsMessage {
 
     #[serde(rename = "err")]
     Error(WsError),
+
+    #[serde(rename = "prg")]
+    Progress(WsProgress),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 #[serde(deny_unknown_fields)]
 pub struct WsRequest {
     pub id: Arc<String>,
+    pub method: Arc<String>,
 
-    #[serde(flatten)]
-    pub method: WsRequestMethod,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub params: Option<Value>,
 }
 
 impl WsRequest {
-    pub fn new(id: Arc<String>, method: WsRequestMethod) -> WsRequest {
+    pub fn new(id: Arc<String>, method: Arc<String>, params: Option<Value>) -> WsRequest {
         WsRequest {
             id,
             method,
+            params,
         }
     }
+
+    pub fn with_params<P: Serialize>(id: Arc<String>, method: &str, params: &P) -> Result<WsRequest, serde_json::Error> {
+        Ok(WsRequest {
+            id,
+            method: Arc::new(method.to_string()),
+            params: Some(serde_json::to_value(params)?),
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -53,10 +74,10 @@ pub struct WsNotification {
 }
 
 impl WsNotification {
-    pub fn new(method: WsNotificationMethod) -> WsMessage {
-        WsMessage::Notification(WsNotification {
+    pub fn new(method: WsNotificationMethod) -> WsNotification {
+        WsNotification {
             method,
-        })
+        }
     }
 }
 
@@ -72,14 +93,14 @@ impl WsResponse {
     pub fn pong_response(id: Arc<String>) -> WsResponse {
         WsResponse {
             id,
-            result: WsResponseResult::Text(PONG_STRING.clone()),
+            result: WsResponseResult::pong(),
         }
     }
 
     pub fn ok_response(id: Arc<String>) -> WsResponse {
         WsResponse {
             id,
-            result: WsResponseResult::Text(OK_STRING.clone()),
+            result: WsResponseResult::ok(),
         }
     }
 
@@ -103,6 +124,63 @@ impl WsResponse {
             result,
         }
     }
+
+    pub fn value_response<T: Serialize>(id: Arc<String>, result: &T) -> Result<WsResponse, serde_json::Error> {
+        Ok(WsResponse {
+            id,
+            result: WsResponseResult::from_serializable(result)?,
+        })
+    }
+
+    /// Deserializes the result into the expected type.
+    pub fn result_as<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        match &self.result {
+            WsResponseResult::Text(text) => serde_json::from_value(Value::String(text.to_string())),
+            WsResponseResult::Value(value) => T::deserialize(value),
+        }
+    }
+}
+
+/// An intermediate message of a request that is still being processed.
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct WsProgress {
+    pub id: Arc<String>,
+
+    // The completed fraction of the work, between 0 and 1.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress: Option<f64>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<Arc<String>>,
+
+    // A part of the result.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partial: Option<Value>,
+}
+
+impl WsProgress {
+    pub fn progress(id: Arc<String>, progress: f64, message: Option<Arc<String>>) -> WsProgress {
+        WsProgress {
+            id,
+            progress: Some(progress.clamp(0.0, 1.0)),
+            message,
+            partial: None,
+        }
+    }
+
+    pub fn partial(id: Arc<String>, partial: Value) -> WsProgress {
+        WsProgress {
+            id,
+            progress: None,
+            message: None,
+            partial: Some(partial),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -113,11 +191,39 @@ pub struct WsError {
     pub id: Option<Arc<String>>,
     pub eid: WsErrorId,
     pub message: Arc<String>,
+
+    // Extra information about the error.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, JsonSchema)]
 pub enum WsErrorId {
+    // The message cannot be decoded.
+    ParseError,
+    // The message is not valid.
     IncorrectInput,
+    // The requested method does not exist.
+    MethodNotFound,
+    // The params do not match the ones expected by the method.
+    InvalidParams,
+    // The client is not allowed to perform the request.
+    Unauthorized,
+    // The request was not answered in time.
+    Timeout,
+    // The request was cancelled by its sender.
+    Cancelled,
+    // The other side disconnected before answering the request.
+    Disconnected,
+    // The requested element does not exist.
+    NotFound,
+    // The request collides with the current state.
+    Conflict,
+    // The client sent too many requests.
+    RateLimited,
+    // An unexpected error.
+    Internal,
 }
 
 impl WsError {
@@ -126,6 +232,7 @@ impl WsError {
             id: Some(id),
             eid,
             message,
+            data: None,
         }
     }
 
@@ -134,6 +241,7 @@ impl WsError {
             id: None,
             eid,
             message,
+            data: None,
         }
     }
 
@@ -142,6 +250,7 @@ impl WsError {
             id: Some(id),
             eid,
             message: Arc::new(message),
+            data: None,
         }
     }
 
@@ -150,43 +259,201 @@ impl WsError {
             id: None,
             eid,
             message: Arc::new(message),
+            data: None,
         }
     }
+
+    pub fn with_id(mut self, id: Arc<String>) -> WsError {
+        self.id = Some(id);
+        self
+    }
+
+    pub fn with_data(mut self, data: Value) -> WsError {
+        self.data = Some(data);
+        self
+    }
 }
 
-// ----------------------------------------------------------------------------
-// ----------------------------------------------------------------------------
-// ----------------------------------------------------------------------------
+/// Errors that can be sent to the client. Implementing this trait allows methods to
+/// return them with `?`, the id of the request being filled in by the dispatcher.
+pub trait ToWsError: Display {
+    fn error_id(&self) -> WsErrorId;
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(tag = "method", content = "params")]
-#[serde(rename_all = "camelCase")]
-#[serde(deny_unknown_fields)]
-pub enum WsRequestMethod {
-    Ping,
-    Echo(Arc<String>),
-    Shutdown,
+    fn error_data(&self) -> Option<Value> {
+        None
+    }
+}
+
+impl<E: ToWsError> From<E> for WsError {
+    fn from(error: E) -> Self {
+        WsError {
+            id: None,
+            eid: error.error_id(),
+            message: Arc::new(error.to_string()),
+            data: error.error_data(),
+        }
+    }
+}
+
+impl ToWsError for serde_json::Error {
+    fn error_id(&self) -> WsErrorId {
+        WsErrorId::InvalidParams
+    }
+}
+
+impl ToWsError for Box<dyn Error + Send> {
+    fn error_id(&self) -> WsErrorId {
+        WsErrorId::Internal
+    }
 }
 
 // ----------------------------------------------------------------------------
 // ----------------------------------------------------------------------------
 // ----------------------------------------------------------------------------
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
 #[serde(tag = "method", content = "params")]
 #[serde(rename_all = "camelCase")]
 #[serde(deny_unknown_fields)]
 pub enum WsNotificationMethod {
     AskMe(Arc<String>),
+    Cancel(WsCancelParams),
+    Hello(WsHelloParams),
+    Ack(WsAckParams),
+    Auth(WsAuthParams),
+}
+
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct WsCancelParams {
+    // The id of the request to cancel.
+    pub id: Arc<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct WsHelloParams {
+    // The version of the protocol.
+    pub version: u32,
+
+    // The optional parts of the protocol supported.
+    #[serde(default)]
+    pub features: Vec<String>,
+
+    // The request methods that can be called.
+    #[serde(default)]
+    pub methods: Vec<String>,
+
+    // The session of the client, only sent by the server.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<WsHelloSession>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct WsHelloSession {
+    // The token to present to resume the session.
+    pub token: Arc<String>,
+
+    // Whether the connection resumed a previous session.
+    pub resumed: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct WsAckParams {
+    // The sequence number of the last frame received.
+    pub seq: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct WsAuthParams {
+    // The token received when logging in.
+    pub token: Arc<String>,
+}
+
+/// The reasons the core can close a websocket connection with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WsCloseReason {
+    IncompatibleVersion,
+    IdleTimeout,
+    OutboxFull,
+    RateLimited,
+    MessageTooBig,
+    Unauthenticated,
+    TooManyConnections,
+}
+
+impl WsCloseReason {
+    pub fn code(&self) -> u16 {
+        match self {
+            WsCloseReason::IncompatibleVersion => 4000,
+            WsCloseReason::IdleTimeout => 4001,
+            WsCloseReason::OutboxFull => 4002,
+            WsCloseReason::RateLimited => 4003,
+            WsCloseReason::MessageTooBig => 1009,
+            WsCloseReason::Unauthenticated => 4004,
+            WsCloseReason::TooManyConnections => 1013,
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            WsCloseReason::IncompatibleVersion => "INCOMPATIBLE_PROTOCOL_VERSION",
+            WsCloseReason::IdleTimeout => "IDLE_TIMEOUT",
+            WsCloseReason::OutboxFull => "OUTBOUND_QUEUE_FULL",
+            WsCloseReason::RateLimited => "RATE_LIMITED",
+            WsCloseReason::MessageTooBig => "MESSAGE_TOO_BIG",
+            WsCloseReason::Unauthenticated => "UNAUTHENTICATED",
+            WsCloseReason::TooManyConnections => "TOO_MANY_CONNECTIONS",
+        }
+    }
 }
 
 // ----------------------------------------------------------------------------
 // ----------------------------------------------------------------------------
 // ----------------------------------------------------------------------------
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
 #[serde(untagged)]
 #[serde(deny_unknown_fields)]
 pub enum WsResponseResult {
     Text(Arc<String>),
+    Value(Value),
+}
+
+impl WsResponseResult {
+    pub fn pong() -> WsResponseResult {
+        WsResponseResult::Text(PONG_STRING.clone())
+    }
+
+    pub fn ok() -> WsResponseResult {
+        WsResponseResult::Text(OK_STRING.clone())
+    }
+
+    pub fn from_serializable<T: Serialize>(result: &T) -> Result<WsResponseResult, serde_json::Error> {
+        match serde_json::to_value(result)? {
+            Value::String(text) => Ok(WsResponseResult::Text(Arc::new(text))),
+            value => Ok(WsResponseResult::Value(value)),
+        }
+    }
+}
+
+impl From<Arc<String>> for WsResponseResult {
+    fn from(text: Arc<String>) -> Self {
+        WsResponseResult::Text(text)
+    }
+}
+
+impl From<Value> for WsResponseResult {
+    fn from(value: Value) -> Self {
+        WsResponseResult::Value(value)
+    }
 }
\ No newline at end of file
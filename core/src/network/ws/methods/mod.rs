@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use schemars::gen::SchemaGenerator;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::application::AppContextRef;
+use crate::auth::roles::AuthPermission;
+use crate::network::ws::messages::{WsError, WsErrorId, WsMessage, WsProgress, WsResponseResult};
+use crate::network::ws::protocol::WsMethodDescription;
+use crate::network::ws::send_ws_message;
+
+pub mod system;
+pub mod topics;
+
+pub type WsMethodFuture<T> = Pin<Box<dyn Future<Output = Result<T, WsError>> + Send>>;
+
+/// A method that the client can call through a websocket request.
+pub trait WsRequestMethod: Send + Sync + 'static {
+    /// The name of the method as it appears in the `method` field of the request.
+    const NAME: &'static str;
+
+    /// Whether the requests to this method must be processed one after another in
+    /// the order they are received instead of concurrently.
+    const SEQUENTIAL: bool = false;
+
+    /// What the client needs to call this method when authentication is enabled.
+    const PERMISSION: AuthPermission = AuthPermission::Write;
+
+    type Params: DeserializeOwned + JsonSchema + Send + 'static;
+    type Result: Serialize + JsonSchema + Send + 'static;
+
+    fn handle(&self, context: WsMethodContext, params: Self::Params) -> WsMethodFuture<Self::Result>;
+}
+
+/// The information available to a method while handling a request.
+pub struct WsMethodContext {
+    pub request_id: Arc<String>,
+    pub connection_id: Arc<String>,
+    pub context_ref: AppContextRef,
+}
+
+impl WsMethodContext {
+    /// Tells the client how much of the request has been completed, from 0 to 1.
+    pub async fn report_progress(&self, progress: f64, message: Option<String>) -> Result<(), Box<dyn Error + Send>> {
+        let progress = WsProgress::progress(self.request_id.clone(), progress, message.map(Arc::new));
+        send_ws_message(&self.connection_id, WsMessage::Progress(progress), &self.context_ref).await
+    }
+
+    /// Sends a part of the result to the client before the final response.
+    pub async fn send_partial_result<T: Serialize>(&self, partial: &T) -> Result<(), Box<dyn Error + Send>> {
+        let partial = match serde_json::to_value(partial) {
+            Ok(v) => v,
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        send_ws_message(&self.connection_id, WsMessage::Progress(WsProgress::partial(self.request_id.clone(), partial)), &self.context_ref).await
+    }
+}
+
+/// The set of methods available to the clients.
+#[derive(Default)]
+pub struct WsMethodRegistry {
+    methods: HashMap<&'static str, Box<dyn WsMethodHandler>>,
+}
+
+impl WsMethodRegistry {
+    pub fn new() -> WsMethodRegistry {
+        WsMethodRegistry {
+            methods: HashMap::new(),
+        }
+    }
+
+    /// Creates a registry with all the methods of the application.
+    pub fn with_defaults() -> WsMethodRegistry {
+        let mut registry = WsMethodRegistry::new();
+        system::register(&mut registry);
+        topics::register(&mut registry);
+        registry
+    }
+
+    pub fn register<M: WsRequestMethod>(&mut self, method: M) -> &mut Self {
+        if self.methods.insert(M::NAME, Box::new(TypedMethodHandler(method))).is_some() {
+            warn!("Method {} registered twice, keeping the last one", M::NAME);
+        }
+
+        self
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.methods.contains_key(name)
+    }
+
+    pub fn is_sequential(&self, name: &str) -> bool {
+        self.methods.get(name).is_some_and(|handler| handler.is_sequential())
+    }
+
+    /// The permission needed to call `name`, if it exists.
+    pub fn permission(&self, name: &str) -> Option<AuthPermission> {
+        self.methods.get(name).map(|handler| handler.permission())
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.methods.keys().copied()
+    }
+
+    /// Describes every method with the JSON Schemas of its params and result, adding the
+    /// schemas they reference to `generator`.
+    pub fn describe(&self, generator: &mut SchemaGenerator) -> Vec<WsMethodDescription> {
+        let mut methods: Vec<_> = self.methods.iter().collect();
+        methods.sort_by_key(|(name, _)| *name);
+        methods.into_iter().map(|(name, handler)| handler.describe(name, generator)).collect()
+    }
+
+    pub fn dispatch(&self, context: WsMethodContext, method: &str, params: Option<Value>) -> WsMethodFuture<WsResponseResult> {
+        match self.methods.get(method) {
+            Some(handler) => {
+                // Methods can omit the id of their errors.
+                let request_id = context.request_id.clone();
+                let future = handler.call(context, params);
+                Box::pin(async move {
+                    future.await.map_err(|e| match e.id {
+                        Some(_) => e,
+                        None => e.with_id(request_id),
+                    })
+                })
+            }
+            None => {
+                let error = WsError::new_string(context.request_id, WsErrorId::MethodNotFound, format!("Method not found: {}", method));
+                Box::pin(async move { Err(error) })
+            }
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// ----------------------------------------------------------------------------
+// ----------------------------------------------------------------------------
+
+trait WsMethodHandler: Send + Sync {
+    fn is_sequential(&self) -> bool;
+
+    fn permission(&self) -> AuthPermission;
+
+    fn describe(&self, name: &str, generator: &mut SchemaGenerator) -> WsMethodDescription;
+
+    fn call(&self, context: WsMethodContext, params: Option<Value>) -> WsMethodFuture<WsResponseResult>;
+}
+
+struct TypedMethodHandler<M>(M);
+
+impl<M: WsRequestMethod> WsMethodHandler for TypedMethodHandler<M> {
+    fn is_sequential(&self) -> bool {
+        M::SEQUENTIAL
+    }
+
+    fn permission(&self) -> AuthPermission {
+        M::PERMISSION
+    }
+
+    fn describe(&self, name: &str, generator: &mut SchemaGenerator) -> WsMethodDescription {
+        WsMethodDescription {
+            name: name.to_string(),
+            sequential: Some(M::SEQUENTIAL),
+            permission: Some(M::PERMISSION),
+            params: generator.subschema_for::<M::Params>(),
+            result: Some(generator.subschema_for::<M::Result>()),
+        }
+    }
+
+    fn call(&self, context: WsMethodContext, params: Option<Value>) -> WsMethodFuture<WsResponseResult> {
+        let params: M::Params = match read_params(params) {
+            Ok(v) => v,
+            Err(e) => {
+                let error = WsError::from(e).with_id(context.request_id);
+                return Box::pin(async move { Err(error) });
+            }
+        };
+
+        let future = self.0.handle(context, params);
+        Box::pin(async move {
+            let result = future.await?;
+            WsResponseResult::from_serializable(&result).map_err(|e| WsError::new_no_id_string(WsErrorId::Internal, format!("Cannot serialize the result: {}", e)))
+        })
+    }
+}
+
+/// Deserializes the params of a method. Positional params, as JSON-RPC clients send them,
+/// also hold the value of the methods that take a single value, or none if they are empty.
+pub fn read_params<P: DeserializeOwned>(params: Option<Value>) -> Result<P, serde_json::Error> {
+    match params {
+        Some(Value::Array(values)) if values.len() <= 1 => serde_json::from_value(Value::Array(values.clone())).or_else(|e| {
+            serde_json::from_value(values.into_iter().next().unwrap_or(Value::Null)).map_err(|_| e)
+        }),
+        params => serde_json::from_value(params.unwrap_or(Value::Null)),
+    }
+}
@@ -0,0 +1,80 @@
+use std::sync::Arc;
+
+use crate::auth::roles::AuthPermission;
+use crate::network::ws::messages::{OK_STRING, PONG_STRING};
+use crate::network::ws::protocol::{describe_protocol, WsProtocolDescription};
+use crate::network::ws::methods::{WsMethodContext, WsMethodFuture, WsMethodRegistry, WsRequestMethod};
+
+pub fn register(registry: &mut WsMethodRegistry) {
+    registry.register(PingMethod).register(EchoMethod).register(ShutdownMethod).register(DiscoverMethod);
+}
+
+pub struct PingMethod;
+
+impl WsRequestMethod for PingMethod {
+    const NAME: &'static str = "ping";
+    const PERMISSION: AuthPermission = AuthPermission::Read;
+
+    type Params = ();
+    type Result = Arc<String>;
+
+    fn handle(&self, _context: WsMethodContext, _params: ()) -> WsMethodFuture<Arc<String>> {
+        trace!("[METHOD] Ping");
+        Box::pin(async { Ok(PONG_STRING.clone()) })
+    }
+}
+
+pub struct EchoMethod;
+
+impl WsRequestMethod for EchoMethod {
+    const NAME: &'static str = "echo";
+    const PERMISSION: AuthPermission = AuthPermission::Read;
+
+    type Params = Arc<String>;
+    type Result = Arc<String>;
+
+    fn handle(&self, _context: WsMethodContext, text: Arc<String>) -> WsMethodFuture<Arc<String>> {
+        trace!("[METHOD] Echo");
+        Box::pin(async { Ok(text) })
+    }
+}
+
+pub struct ShutdownMethod;
+
+impl WsRequestMethod for ShutdownMethod {
+    const NAME: &'static str = "shutdown";
+    const PERMISSION: AuthPermission = AuthPermission::Admin;
+
+    type Params = ();
+    type Result = Arc<String>;
+
+    fn handle(&self, context: WsMethodContext, _params: ()) -> WsMethodFuture<Arc<String>> {
+        trace!("[METHOD] Shutdown");
+        Box::pin(async move {
+            info!("Shutting down");
+
+            // Shut down once the response has been sent.
+            context.context_ref.lock().await.shutdown_request = Some((context.connection_id, context.request_id));
+
+            Ok(OK_STRING.clone())
+        })
+    }
+}
+
+pub struct DiscoverMethod;
+
+impl WsRequestMethod for DiscoverMethod {
+    const NAME: &'static str = "rpc.discover";
+    const PERMISSION: AuthPermission = AuthPermission::Read;
+
+    type Params = ();
+    type Result = WsProtocolDescription;
+
+    fn handle(&self, context: WsMethodContext, _params: ()) -> WsMethodFuture<WsProtocolDescription> {
+        trace!("[METHOD] Discover");
+        Box::pin(async move {
+            let methods = context.context_ref.lock().await.methods.clone();
+            Ok(describe_protocol(&methods))
+        })
+    }
+}
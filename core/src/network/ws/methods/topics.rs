@@ -0,0 +1,154 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::auth::roles::AuthPermission;
+use crate::network::ws::messages::{OK_STRING, WsError, WsErrorId};
+use crate::network::ws::methods::{WsMethodContext, WsMethodFuture, WsMethodRegistry, WsRequestMethod};
+
+pub fn register(registry: &mut WsMethodRegistry) {
+    registry.register(SubscribeMethod).register(UnsubscribeMethod);
+}
+
+#[derive(Deserialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct TopicsRequest {
+    pub topics: Vec<String>,
+}
+
+pub struct SubscribeMethod;
+
+impl WsRequestMethod for SubscribeMethod {
+    const NAME: &'static str = "subscribe";
+    const PERMISSION: AuthPermission = AuthPermission::Read;
+
+    type Params = TopicsRequest;
+    type Result = Arc<String>;
+
+    fn handle(&self, context: WsMethodContext, params: TopicsRequest) -> WsMethodFuture<Arc<String>> {
+        trace!("[METHOD] Subscribe");
+        Box::pin(async move {
+            if let Some(topic) = params.topics.iter().find(|v| !is_valid_topic_pattern(v)) {
+                return Err(WsError::new_no_id_string(WsErrorId::InvalidParams, format!("Invalid topic: {}", topic)));
+            }
+
+            let mut app_context = context.context_ref.lock().await;
+            let max_subscriptions = app_context.max_subscriptions;
+            if let Some(client) = app_context.client_mut(&context.connection_id) {
+                let new_topics = params.topics.iter().filter(|v| !client.subscriptions.contains(*v)).collect::<HashSet<_>>().len();
+                if client.subscriptions.len() + new_topics > max_subscriptions {
+                    return Err(WsError::new_no_id_string(WsErrorId::Conflict, format!("Cannot subscribe to more than {} topics", max_subscriptions)));
+                }
+
+                client.subscriptions.extend(params.topics);
+            }
+
+            Ok(OK_STRING.clone())
+        })
+    }
+}
+
+pub struct UnsubscribeMethod;
+
+impl WsRequestMethod for UnsubscribeMethod {
+    const NAME: &'static str = "unsubscribe";
+    const PERMISSION: AuthPermission = AuthPermission::Read;
+
+    type Params = TopicsRequest;
+    type Result = Arc<String>;
+
+    fn handle(&self, context: WsMethodContext, params: TopicsRequest) -> WsMethodFuture<Arc<String>> {
+        trace!("[METHOD] Unsubscribe");
+        Box::pin(async move {
+            let mut app_context = context.context_ref.lock().await;
+            if let Some(client) = app_context.client_mut(&context.connection_id) {
+                for topic in &params.topics {
+                    client.subscriptions.remove(topic);
+                }
+            }
+
+            Ok(OK_STRING.clone())
+        })
+    }
+}
+
+// ----------------------------------------------------------------------------
+// ----------------------------------------------------------------------------
+// ----------------------------------------------------------------------------
+
+/// Checks whether a topic pattern is well formed, i.e. it is made of non-empty
+/// dot-separated segments where `**` can only appear as the last one.
+pub fn is_valid_topic_pattern(pattern: &str) -> bool {
+    let segments: Vec<&str> = pattern.split('.').collect();
+    let last = segments.len() - 1;
+
+    segments.iter().enumerate().all(|(i, segment)| !segment.is_empty() && (*segment != "**" || i == last))
+}
+
+/// Checks whether `topic` matches `pattern`. In a pattern, `*` matches exactly one
+/// segment and a trailing `**` matches one or more segments, e.g. `projects.*` matches
+/// `projects.created` but not `projects.1.deleted`, which is matched by `projects.**`.
+pub fn topic_matches(pattern: &str, topic: &str) -> bool {
+    let mut pattern_segments = pattern.split('.');
+    let mut topic_segments = topic.split('.');
+
+    loop {
+        match (pattern_segments.next(), topic_segments.next()) {
+            (Some("**"), Some(_)) => return true,
+            (Some("*"), Some(_)) => {}
+            (Some(p), Some(t)) if p == t => {}
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // `tokio` exports its own `test` macro.
+    use std::prelude::v1::test;
+
+    use super::*;
+
+    #[test]
+    fn test_single_segment_wildcard() {
+        assert!(topic_matches("projects.*", "projects.created"));
+        assert!(!topic_matches("projects.*", "projects.1.deleted"));
+        assert!(!topic_matches("projects.*", "projects"));
+        assert!(topic_matches("projects.*.deleted", "projects.1.deleted"));
+    }
+
+    #[test]
+    fn test_trailing_multi_segment_wildcard() {
+        assert!(is_valid_topic_pattern("projects.**"));
+        assert!(topic_matches("projects.**", "projects.created"));
+        assert!(topic_matches("projects.**", "projects.1.deleted"));
+        assert!(!topic_matches("projects.**", "projects"));
+        assert!(!topic_matches("projects.**", "users.created"));
+    }
+
+    #[test]
+    fn test_multi_segment_wildcard_in_the_middle() {
+        assert!(!is_valid_topic_pattern("projects.**.deleted"));
+        assert!(!is_valid_topic_pattern("**.deleted"));
+    }
+
+    #[test]
+    fn test_empty_segment() {
+        assert!(!is_valid_topic_pattern(""));
+        assert!(!is_valid_topic_pattern("projects..created"));
+        assert!(!is_valid_topic_pattern("projects."));
+        assert!(!is_valid_topic_pattern(".projects"));
+    }
+
+    #[test]
+    fn test_exact_topic() {
+        assert!(is_valid_topic_pattern("projects.created"));
+        assert!(topic_matches("projects.created", "projects.created"));
+        assert!(!topic_matches("projects.created", "projects.deleted"));
+        assert!(!topic_matches("projects.created", "projects.created.1"));
+    }
+}
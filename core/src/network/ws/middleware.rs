@@ -0,0 +1,115 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use warp::http::Extensions;
+
+use crate::application::AppClient;
+use crate::network::ws::messages::{WsError, WsMessage, WsRequest, WsResponse};
+
+/// The state of a request shared by the hooks of the middlewares while it is processed.
+pub struct WsRequestContext {
+    // When the request started being processed.
+    pub start: Instant,
+    // Values that the `before` hooks store for the `after` and `on_error` hooks.
+    pub extensions: Extensions,
+}
+
+impl WsRequestContext {
+    pub fn new() -> WsRequestContext {
+        WsRequestContext {
+            start: Instant::now(),
+            extensions: Extensions::new(),
+        }
+    }
+}
+
+impl Default for WsRequestContext {
+    fn default() -> Self {
+        WsRequestContext::new()
+    }
+}
+
+/// Hooks around the processing of every request of the clients. The hooks run while the
+/// context is locked, so they must not block.
+pub trait WsMiddleware: Send + Sync + 'static {
+    /// Runs before the method and can update the state of the client. Returning an error
+    /// answers the request with it without running the method nor the following middlewares.
+    fn before(&self, _connection_id: &Arc<String>, _client: &mut AppClient, _request: &WsRequest, _request_context: &mut WsRequestContext) -> Result<(), WsError> {
+        Ok(())
+    }
+
+    /// Runs after the method succeeds, before the response is sent. The client is `None`
+    /// if it disconnected while the request was processed.
+    fn after(&self, _connection_id: &Arc<String>, _client: Option<&AppClient>, _request: &WsRequest, _request_context: &mut WsRequestContext, _response: &mut WsResponse) {}
+
+    /// Runs after the method or a following middleware fails, before the error is sent. The
+    /// client is `None` if it disconnected while the request was processed.
+    fn on_error(&self, _connection_id: &Arc<String>, _client: Option<&AppClient>, _request: &WsRequest, _request_context: &mut WsRequestContext, _error: &mut WsError) {}
+}
+
+/// The middlewares of the server. The `before` hooks run in the order the middlewares are
+/// added, while the `after` and `on_error` hooks run in reverse order and only for the
+/// middlewares whose `before` hook accepted the request, even if the client disconnected.
+#[derive(Default)]
+pub struct WsMiddlewareChain {
+    middlewares: Vec<Box<dyn WsMiddleware>>,
+}
+
+impl WsMiddlewareChain {
+    pub fn new() -> WsMiddlewareChain {
+        WsMiddlewareChain {
+            middlewares: Vec::new(),
+        }
+    }
+
+    pub fn add<M: WsMiddleware>(&mut self, middleware: M) -> &mut Self {
+        self.middlewares.push(Box::new(middleware));
+        self
+    }
+
+    /// Runs the `before` hooks returning how many middlewares accepted the request
+    /// and the error of the one that rejected it, if any.
+    pub fn before(&self, connection_id: &Arc<String>, client: &mut AppClient, request: &WsRequest, request_context: &mut WsRequestContext) -> (usize, Result<(), WsError>) {
+        for (index, middleware) in self.middlewares.iter().enumerate() {
+            if let Err(error) = middleware.before(connection_id, client, request, request_context) {
+                let error = match error.id {
+                    Some(_) => error,
+                    None => error.with_id(request.id.clone()),
+                };
+
+                return (index, Err(error));
+            }
+        }
+
+        (self.middlewares.len(), Ok(()))
+    }
+
+    /// Runs the `after` or `on_error` hooks of the first `entered` middlewares depending on the answer.
+    pub fn after(&self, entered: usize, connection_id: &Arc<String>, client: Option<&AppClient>, request: &WsRequest, request_context: &mut WsRequestContext, message: &mut WsMessage) {
+        for middleware in self.middlewares[..entered].iter().rev() {
+            match message {
+                WsMessage::Response(response) => middleware.after(connection_id, client, request, request_context, response),
+                WsMessage::Error(error) => middleware.on_error(connection_id, client, request, request_context, error),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Logs every request with its outcome and how long it took.
+pub struct WsLoggingMiddleware;
+
+impl WsMiddleware for WsLoggingMiddleware {
+    fn before(&self, connection_id: &Arc<String>, _client: &mut AppClient, request: &WsRequest, _request_context: &mut WsRequestContext) -> Result<(), WsError> {
+        debug!("Client {} calls {} in request {}", connection_id, request.method, request.id);
+        Ok(())
+    }
+
+    fn after(&self, connection_id: &Arc<String>, _client: Option<&AppClient>, request: &WsRequest, request_context: &mut WsRequestContext, _response: &mut WsResponse) {
+        debug!("Request {} of {} succeeded in {:?}", request.id, connection_id, request_context.start.elapsed());
+    }
+
+    fn on_error(&self, connection_id: &Arc<String>, _client: Option<&AppClient>, request: &WsRequest, request_context: &mut WsRequestContext, error: &mut WsError) {
+        debug!("Request {} of {} failed in {:?} with {:?}: {}", request.id, connection_id, request_context.start.elapsed(), error.eid, error.message);
+    }
+}
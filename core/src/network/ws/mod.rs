@@ -1,122 +1,376 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use futures::{FutureExt, StreamExt};
-use serde::Deserialize;
-use serde::export::Option::Some;
-use serde_json::from_str;
-use tokio::sync::mpsc;
+use futures::{SinkExt, StreamExt};
+use futures::future::{AbortHandle, Abortable};
+use tokio::sync::Notify;
 use uuid::Uuid;
 use warp::ws::{Message, WebSocket};
 
-use crate::application::{AppClient, AppContextRef};
+use crate::auth::AuthIdentity;
+use crate::application::{AppClient, AppContext, AppContextRef, WsAnswerReceiver};
+use crate::network::ws::codec::{WsCodec, WsFrame};
+use crate::network::ws::dispatcher::WsRequestDispatcher;
+use crate::network::ws::encoding::WsEncoding;
 use crate::network::ws::errors::ClientDisconnectedError;
-use crate::network::ws::messages::{WsError, WsErrorId, WsMessage, WsNotification, WsNotificationMethod, WsRequest, WsRequestMethod, WsResponse};
-
+use crate::network::ws::outbox::{WsOutbox, WsOutboxPolicy};
+use crate::network::ws::messages::{WsCancelParams, WsCloseReason, WsError, WsErrorId, WsHelloParams, WsHelloSession, WsMessage, WsNotification, WsNotificationMethod, WsRequest};
+use crate::network::ws::methods::system::EchoMethod;
+use crate::network::ws::methods::WsRequestMethod;
+use crate::network::ws::rate_limit::WsRateLimiter;
+use crate::network::ws::session::{WsResumeParams, WsSession};
+
+pub mod codec;
+pub mod encoding;
+pub mod errors;
 pub mod messages;
-mod errors;
-
-#[derive(Deserialize, Debug)]
-pub struct TopicsRequest {
-    topics: Vec<String>,
+pub mod methods;
+pub mod middleware;
+pub mod protocol;
+pub mod rate_limit;
+pub mod session;
+mod dispatcher;
+mod heartbeat;
+pub mod outbox;
+
+// The time the server waits for the close frame to be sent when it closes a connection.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+// The start of the error of the websocket when a message or frame exceeds its limits.
+const MESSAGE_TOO_BIG_ERROR: &str = "Space limit exceeded";
+
+/// What the client presented when opening its websocket.
+pub struct WsHandshake {
+    pub origin: SocketAddr,
+    pub codec: WsCodec,
+    pub encoding: WsEncoding,
+    // The session to resume, if any.
+    pub resume: Option<WsResumeParams>,
+    // Who the client authenticated as with the token of the URL, if any.
+    pub identity: Option<AuthIdentity>,
 }
 
-pub async fn websocket_connection(ws: WebSocket, connection_id: String, origin: SocketAddr, context_ref: AppContextRef) {
-    let (client_ws_sender, mut client_ws_rcv) = ws.split();
-    let (client_sender, client_rcv) = mpsc::unbounded_channel();
-
-    // Redirect messages.
-    tokio::task::spawn(client_rcv.forward(client_ws_sender).map(|result| {
-        if let Err(e) = result {
-            error!("error sending websocket msg: {}", e);
-        }
-    }));
+pub async fn websocket_connection(ws: WebSocket, socket_id: String, handshake: WsHandshake, context_ref: AppContextRef) {
+    let WsHandshake { origin, codec, encoding, resume, identity } = handshake;
+    let socket_id = Arc::new(socket_id);
+    let (mut client_ws_sender, mut client_ws_rcv) = ws.split();
 
-    // Add to context.
-    {
+    // Add to context, resuming the previous session of the client if possible.
+    let (connection_abort, connection_registration) = AbortHandle::new_pair();
+    let accepted = {
         let mut context = context_ref.lock().await;
-        context.client = Some(AppClient {
+        let client = AppClient {
             origin,
-            sender: client_sender,
+            socket_id: socket_id.clone(),
+            connection_abort: connection_abort.clone(),
+            codec,
+            encoding,
+            outbox: WsOutbox::new(context.outbox_capacity, context.outbox_policy),
+            overflowed: false,
             pending_answers: HashMap::new(),
-        });
-    }
-
-    info!("Client {} connected", connection_id);
+            running_requests: HashMap::new(),
+            subscriptions: HashSet::new(),
+            hello: None,
+            last_seen: Instant::now(),
+            rate_limiter: WsRateLimiter::default(),
+            auth: identity,
+            session: WsSession::new(Arc::new(Uuid::new_v4().to_hyphenated().to_string()), context.session_buffer_size),
+        };
 
-    while let Some(result) = client_ws_rcv.next().await {
-        let msg = match result {
-            Ok(msg) => msg,
-            Err(e) => {
-                error!("Error while receiving ws message: {}", e);
-                break;
+        let connection_id = match resume.and_then(|resume| resume_session(&mut context, &client, &resume).map_err(|e| warn!("Cannot resume session: {}", e)).ok()) {
+            Some(connection_id) => {
+                info!("Client {} resumed its session", connection_id);
+                Some(connection_id)
+            }
+            None if context.accepts_clients() => {
+                let connection_id = socket_id.clone();
+                start_session(&mut context, connection_id.clone(), client);
+                info!("Client {} connected", connection_id);
+                Some(connection_id)
             }
+            None => None,
         };
 
-        parse_client_message(&connection_id, msg, &context_ref).await;
+        connection_id.map(|connection_id| {
+            let dispatcher = WsRequestDispatcher::new(connection_id.clone(), context_ref.clone(), context.max_concurrent_requests);
+            (connection_id, dispatcher)
+        })
+    };
+
+    // Other clients may have taken the last slots since the upgrade was accepted.
+    let (connection_id, dispatcher) = match accepted {
+        Some(v) => v,
+        None => {
+            warn!("Rejected client {}: too many connections", socket_id);
+            let reason = WsCloseReason::TooManyConnections;
+            if let Err(e) = client_ws_sender.send(Message::close_with(reason.code(), reason.message())).await {
+                debug!("Cannot send the close frame to client {}: {}", socket_id, e);
+            }
+
+            return;
+        }
+    };
+
+    // Redirect messages.
+    let (writer_abort, writer_registration) = AbortHandle::new_pair();
+    let mut writer = tokio::spawn(Abortable::new(outbox::run_outbox_writer(connection_id.clone(), socket_id.clone(), client_ws_sender, context_ref.clone()), writer_registration));
+
+    // Watch the connection is alive.
+    let (heartbeat_abort, heartbeat_registration) = AbortHandle::new_pair();
+    tokio::spawn(Abortable::new(heartbeat::run_heartbeat(connection_id.clone(), socket_id.clone(), context_ref.clone(), connection_abort), heartbeat_registration));
+
+    let receive_messages = async {
+        while let Some(result) = client_ws_rcv.next().await {
+            let msg = match result {
+                Ok(msg) => msg,
+                // The websocket stops reading messages bigger than `max_message_size`, and warp
+                // only exposes the error through its message.
+                Err(e) if e.to_string().starts_with(MESSAGE_TOO_BIG_ERROR) => {
+                    warn!("Client {} sent a message bigger than the limit, disconnecting it: {}", connection_id, e);
+                    if let Some(client) = context_ref.lock().await.client_mut(&connection_id) {
+                        client.close(WsCloseReason::MessageTooBig);
+                    }
+
+                    return true;
+                }
+                Err(e) => {
+                    error!("Error while receiving ws message: {}", e);
+                    break;
+                }
+            };
+
+            if let Some(client) = context_ref.lock().await.client_mut(&connection_id) {
+                client.last_seen = Instant::now();
+            }
+
+            parse_client_message(&connection_id, msg, codec, encoding, &context_ref, &dispatcher).await;
+        }
+
+        false
+    };
+
+    // Whether the server closed the connection.
+    let closed = Abortable::new(receive_messages, connection_registration).await.unwrap_or_else(|_| {
+        debug!("Stopped listening to client {}", connection_id);
+        true
+    });
+
+    if closed && tokio::time::timeout(CLOSE_TIMEOUT, &mut writer).await.is_err() {
+        debug!("Cannot send the close frame to client {}", connection_id);
+    }
+
+    heartbeat_abort.abort();
+    writer_abort.abort();
+    detach_session(connection_id, socket_id, context_ref).await;
+}
+
+/// Adds a new client to the context and sends it the `hello`.
+fn start_session(context: &mut AppContext, connection_id: Arc<String>, mut client: AppClient) {
+    let hello = protocol::server_hello(&context.methods, WsHelloSession { token: client.session.token.clone(), resumed: false });
+    if let Some(msg) = encode_ws_hello(&connection_id, &client, hello) {
+        client.outbox.push_priority(msg);
+    }
+
+    context.clients.insert(connection_id, client);
+}
+
+/// Attaches the connection of `client` to the session it wants to resume, sending it the
+/// `hello` followed by the frames it has not received. Returns the connection id of the session.
+fn resume_session(context: &mut AppContext, client: &AppClient, resume: &WsResumeParams) -> Result<Arc<String>, &'static str> {
+    let connection_id = context.session_connection_id(&resume.token).ok_or("unknown session")?;
+    let hello = protocol::server_hello(&context.methods, WsHelloSession { token: Arc::new(resume.token.clone()), resumed: true });
+    let session_client = context.client_mut(&connection_id).unwrap();
+
+    if session_client.overflowed {
+        return Err("the outbound queue of the session overflowed");
+    }
+
+    if session_client.codec != client.codec || session_client.encoding != client.encoding {
+        return Err("the format of the messages has changed");
+    }
+
+    let mut frames = session_client.session.replay_from(resume.last_seq).ok_or("some frames are not available anymore")?;
+
+    // Take the session over from the previous connection if it is still alive.
+    if session_client.session.is_connected() {
+        session_client.connection_abort.abort();
     }
 
+    session_client.origin = client.origin;
+    session_client.socket_id = client.socket_id.clone();
+    session_client.connection_abort = client.connection_abort.clone();
+    session_client.hello = None;
+    session_client.last_seen = client.last_seen;
+    session_client.session.disconnected_at = None;
+
+    // The session keeps its identity unless the new connection presents another one.
+    if client.auth.is_some() {
+        session_client.auth = client.auth.clone();
+    }
+
+    if let Some(msg) = encode_ws_hello(&connection_id, session_client, hello) {
+        frames.insert(0, msg);
+    }
+
+    session_client.outbox.reset_priority(frames);
+
+    Ok(connection_id)
+}
+
+/// Marks the session of the client as disconnected, removing it if the client does not resume
+/// it within the grace period. Nothing happens if another connection has taken the session over.
+async fn detach_session(connection_id: Arc<String>, socket_id: Arc<String>, context_ref: AppContextRef) {
+    let grace_period = {
+        let mut context = context_ref.lock().await;
+        let grace_period = context.session_grace_period;
+        match context.client_mut(&connection_id) {
+            Some(client) if client.socket_id == socket_id => {
+                if grace_period.as_nanos() == 0 || client.overflowed {
+                    remove_client(&mut context, &connection_id);
+                    return;
+                }
+
+                client.session.disconnected_at = Some(Instant::now());
+            }
+            _ => return,
+        }
+
+        grace_period
+    };
+
+    info!("Client {} lost its connection", connection_id);
+
+    tokio::spawn(async move {
+        tokio::time::delay_for(grace_period).await;
+
+        let mut context = context_ref.lock().await;
+        match context.client(&connection_id) {
+            Some(client) if client.socket_id == socket_id && !client.session.is_connected() => {
+                remove_client(&mut context, &connection_id);
+            }
+            _ => {}
+        }
+    });
+}
+
+fn remove_client(context: &mut AppContext, connection_id: &Arc<String>) {
     // Clean up a bit of memory.
-    context_ref.lock().await.client = None;
+    if let Some(mut client) = context.clients.remove(connection_id) {
+        client.cancel_running_requests();
+        client.fail_pending_answers();
+
+        // Let the producers blocked by the client fail.
+        client.outbox.space_ready.notify();
+    }
 
     info!("Client {} disconnected", connection_id);
 }
 
-async fn parse_client_message(_connection_id: &str, msg: Message, context_ref: &AppContextRef) {
+/// Encodes the `hello`, which is sent outside the session because every connection receives its own.
+fn encode_ws_hello(connection_id: &Arc<String>, client: &AppClient, hello: WsHelloParams) -> Option<Message> {
+    let frame = WsFrame::Single(WsMessage::Notification(WsNotification::new(WsNotificationMethod::Hello(hello))));
+    match client.encode(&frame) {
+        Ok(msg) => Some(msg),
+        Err(e) => {
+            error!("Error while sending hello to {}: {}", connection_id, e);
+            None
+        }
+    }
+}
+
+async fn parse_client_message(connection_id: &Arc<String>, msg: Message, codec: WsCodec, encoding: WsEncoding, context_ref: &AppContextRef, dispatcher: &WsRequestDispatcher) {
     debug!("Received message: {:?}", msg);
-    let message_str = match msg.to_str() {
-        Ok(v) => v,
-        Err(_) => return,
+    let value = match encoding.decode(&msg) {
+        Some(Ok(v)) => v,
+        Some(Err(e)) => {
+            error!("Error while decoding message: {}", e);
+            let _ = send_ws_message(connection_id, WsMessage::Error(WsError::new_no_id_string(WsErrorId::ParseError, e)), context_ref).await;
+            return;
+        }
+        None => return,
     };
 
-    let message: WsMessage = match from_str(message_str) {
+    let (frame, errors) = match codec.read_frame(value) {
         Ok(v) => v,
-        Err(e) => {
-            error!("Error while parsing message: {}", e);
-            let _ = send_ws_message(WsMessage::Error(WsError::new_no_id_string(WsErrorId::IncorrectInput, e.to_string())), context_ref).await;
+        Err(Some(error)) => {
+            error!("Error while parsing message: {}", error.message);
+            let _ = send_ws_message(connection_id, WsMessage::Error(error), context_ref).await;
+            return;
+        }
+        Err(None) => {
+            debug!("Ignoring invalid notification");
             return;
         }
     };
 
-    match message {
-        WsMessage::Request(request) => {
-            let response: Result<WsResponse, WsError> = match request.method {
-                WsRequestMethod::Ping => {
-                    trace!("[METHOD] Ping");
-                    Ok(WsResponse::pong_response(request.id))
-                }
-                WsRequestMethod::Echo(text) => {
-                    trace!("[METHOD] Echo");
-                    Ok(WsResponse::text_response(request.id, text))
-                }
-                WsRequestMethod::Shutdown => {
-                    trace!("[METHOD] Shutdown");
-                    let response = WsResponse::ok_response(request.id);
-                    let _ = send_ws_message(WsMessage::Response(response), context_ref).await;
+    // Until they authenticate, clients can only introduce themselves.
+    let is_authenticated = {
+        let context = context_ref.lock().await;
+        context.client(connection_id).is_some_and(|client| context.is_authenticated(client))
+    };
 
-                    info!("Shutting down");
+    if !is_authenticated && !is_auth_handshake(&frame) {
+        warn!("Client {} sent a message before authenticating, disconnecting it", connection_id);
+        if let Some(client) = context_ref.lock().await.client_mut(connection_id) {
+            client.close(WsCloseReason::Unauthenticated);
+        }
 
-                    context_ref.lock().await.shutdown();
-                    return;
-                }
-            };
+        return;
+    }
 
-            let message = match response {
-                Ok(r) => WsMessage::Response(r),
-                Err(e) => WsMessage::Error(e)
-            };
+    match frame {
+        WsFrame::Single(WsMessage::Request(request)) => {
+            dispatcher.dispatch(request).await;
+        }
+        WsFrame::Single(message) => handle_client_message(connection_id, message, context_ref).await,
+        WsFrame::Batch(messages) => {
+            if messages.is_empty() && errors.is_empty() {
+                let _ = send_ws_message(connection_id, WsMessage::Error(WsError::new_no_id_string(WsErrorId::IncorrectInput, "Empty batch".to_string())), context_ref).await;
+                return;
+            }
+
+            let mut requests = Vec::new();
+            for message in messages {
+                match message {
+                    WsMessage::Request(request) => requests.push(request),
+                    message => handle_client_message(connection_id, message, context_ref).await,
+                }
+            }
 
-            if let Err(e) = send_ws_message(message, context_ref).await {
-                error!("Error while sending response: {}", e)
+            // The invalid messages of the batch are answered with its requests.
+            if !requests.is_empty() {
+                dispatcher.dispatch_batch(requests, errors).await;
+            } else if !errors.is_empty() {
+                let _ = send_ws_messages(connection_id, errors.into_iter().map(WsMessage::Error).collect(), context_ref).await;
             }
         }
+    }
+}
+
+/// Whether the frame only contains the `hello` or `auth` notifications.
+fn is_auth_handshake(frame: &WsFrame) -> bool {
+    let is_handshake = |message: &WsMessage| matches!(message, WsMessage::Notification(WsNotification { method: WsNotificationMethod::Hello(_) | WsNotificationMethod::Auth(_), .. }));
+    match frame {
+        WsFrame::Single(message) => is_handshake(message),
+        WsFrame::Batch(messages) => messages.iter().all(is_handshake),
+    }
+}
+
+/// Handles any message of the client but requests.
+async fn handle_client_message(connection_id: &Arc<String>, message: WsMessage, context_ref: &AppContextRef) {
+    match message {
+        WsMessage::Request(request) => {
+            error!("Unhandled request: {:?}", request);
+        }
         WsMessage::Notification(notification) => {
             match notification.method {
                 WsNotificationMethod::AskMe(text) => {
-                    let connection_id = Arc::new(Uuid::new_v4().to_hyphenated().to_string());
-                    match send_ws_request(WsRequest::new(connection_id, WsRequestMethod::Echo(text)), context_ref).await {
+                    let request_id = Arc::new(Uuid::new_v4().to_hyphenated().to_string());
+                    let request = WsRequest::new(request_id, Arc::new(EchoMethod::NAME.to_string()), Some(text.as_str().into()));
+                    match send_ws_request(connection_id, request, context_ref).await {
                         Ok(rx) => {
                             tokio::spawn(async move {
                                 match rx.await {
@@ -134,6 +388,60 @@ async fn parse_client_message(_connection_id: &str, msg: Message, context_ref: &
                         }
                     }
                 }
+                WsNotificationMethod::Hello(hello) => {
+                    if !protocol::is_compatible_version(hello.version) {
+                        warn!("Client {} uses the incompatible protocol version {}", connection_id, hello.version);
+                        if let Some(client) = context_ref.lock().await.client_mut(connection_id) {
+                            client.close(WsCloseReason::IncompatibleVersion);
+                        }
+
+                        return;
+                    }
+
+                    debug!("Client {} uses protocol version {} with features {:?}", connection_id, hello.version, hello.features);
+                    if let Some(client) = context_ref.lock().await.client_mut(connection_id) {
+                        client.hello = Some(hello);
+                    }
+                }
+                WsNotificationMethod::Auth(params) => {
+                    let mut context = context_ref.lock().await;
+                    let identity = match &context.auth {
+                        Some(auth) => auth.validate(&params.token),
+                        None => {
+                            trace!("Ignoring authentication of {} because it is disabled", connection_id);
+                            return;
+                        }
+                    };
+
+                    if let Some(client) = context.client_mut(connection_id) {
+                        match identity {
+                            Some(identity) => {
+                                debug!("Client {} authenticated as {}", connection_id, identity.user);
+                                client.auth = Some(identity);
+                            }
+                            None => {
+                                warn!("Client {} presented an invalid token, disconnecting it", connection_id);
+                                client.close(WsCloseReason::Unauthenticated);
+                            }
+                        }
+                    }
+                }
+                WsNotificationMethod::Ack(params) => {
+                    if let Some(client) = context_ref.lock().await.client_mut(connection_id) {
+                        client.session.acknowledge(params.seq);
+                    }
+                }
+                WsNotificationMethod::Cancel(params) => {
+                    let mut context = context_ref.lock().await;
+                    let cancelled = match context.client_mut(connection_id) {
+                        Some(client) => client.cancel_running_request(&params.id),
+                        None => false,
+                    };
+
+                    if !cancelled {
+                        trace!("Ignoring cancellation of request {} because it is not running", params.id);
+                    }
+                }
             };
         }
 
@@ -141,7 +449,7 @@ async fn parse_client_message(_connection_id: &str, msg: Message, context_ref: &
             trace!("Received response: {:?}", response);
 
             let mut context = context_ref.lock().await;
-            let client: &mut AppClient = if let Some(client) = &mut context.client {
+            let client: &mut AppClient = if let Some(client) = context.client_mut(connection_id) {
                 client
             } else {
                 error!("Unhandled response: {:?}", response);
@@ -161,7 +469,7 @@ async fn parse_client_message(_connection_id: &str, msg: Message, context_ref: &
                 trace!("Received error: {:?}", error);
 
                 let mut context = context_ref.lock().await;
-                let client: &mut AppClient = if let Some(client) = &mut context.client {
+                let client: &mut AppClient = if let Some(client) = context.client_mut(connection_id) {
                     client
                 } else {
                     error!("Unhandled error: {:?}", error);
@@ -172,6 +480,8 @@ async fn parse_client_message(_connection_id: &str, msg: Message, context_ref: &
                     if let Err(error) = tx.send(Err(error)) {
                         error!("Unhandled error because client is disconnected: {:?}", error);
                     };
+                } else if error.eid == WsErrorId::Cancelled {
+                    trace!("Received cancellation of an already cancelled request: {:?}", error);
                 } else {
                     error!("Unhandled error: {:?}", error);
                 }
@@ -179,16 +489,28 @@ async fn parse_client_message(_connection_id: &str, msg: Message, context_ref: &
                 error!("Received error: {:?}", error);
             }
         }
+        WsMessage::Progress(progress) => {
+            trace!("Ignoring progress: {:?}", progress);
+        }
     }
 }
 
-pub async fn send_ws_request(request: WsRequest, context_ref: &AppContextRef) -> Result<futures::channel::oneshot::Receiver<Result<WsResponse, WsError>>, Box<dyn Error + Send>> {
+/// Sends a request to the client with `connection_id` returning a receiver for its answer.
+/// The receiver resolves with a `Timeout` error if the client does not answer within the
+/// default request timeout.
+pub async fn send_ws_request(connection_id: &Arc<String>, request: WsRequest, context_ref: &AppContextRef) -> Result<WsAnswerReceiver, Box<dyn Error + Send>> {
+    let timeout = context_ref.lock().await.request_timeout;
+    send_ws_request_with_timeout(connection_id, request, timeout, context_ref).await
+}
+
+/// Same as `send_ws_request` but overriding the request timeout.
+pub async fn send_ws_request_with_timeout(connection_id: &Arc<String>, request: WsRequest, timeout: Duration, context_ref: &AppContextRef) -> Result<WsAnswerReceiver, Box<dyn Error + Send>> {
     // Save sender.
     let (tx, rx) = futures::channel::oneshot::channel();
     let mut context = context_ref.lock().await;
-    let connection_id = request.id.clone();
-    if let Some(client) = &mut context.client {
-        client.pending_answers.insert(connection_id.clone(), tx);
+    let request_id = request.id.clone();
+    if let Some(client) = context.client_mut(connection_id) {
+        client.pending_answers.insert(request_id.clone(), tx);
     } else {
         return Err(Box::new(ClientDisconnectedError {}));
     }
@@ -197,37 +519,160 @@ pub async fn send_ws_request(request: WsRequest, context_ref: &AppContextRef) ->
     std::mem::drop(context);
 
     // Send request.
-    if let Err(e) = send_ws_message(WsMessage::Request(request), context_ref).await {
+    if let Err(e) = send_ws_message(connection_id, WsMessage::Request(request), context_ref).await {
         // Remove the receiver.
         let mut context = context_ref.lock().await;
-        if let Some(client) = &mut context.client {
-            client.pending_answers.remove(&connection_id);
+        if let Some(client) = context.client_mut(connection_id) {
+            client.pending_answers.remove(&request_id);
         }
 
         return Err(e);
     }
 
+    // Evict the request if it is not answered in time.
+    let connection_id = connection_id.clone();
+    let context_ref = context_ref.clone();
+    tokio::spawn(async move {
+        tokio::time::delay_for(timeout).await;
+
+        let mut context = context_ref.lock().await;
+        let tx = match context.client_mut(&connection_id) {
+            Some(client) => client.pending_answers.remove(&request_id),
+            None => None,
+        };
+
+        if let Some(tx) = tx {
+            warn!("Request {} to {} timed out", request_id, connection_id);
+
+            let error = WsError::new_string(request_id, WsErrorId::Timeout, format!("No answer received in {} ms", timeout.as_millis()));
+            if let Err(error) = tx.send(Err(error)) {
+                trace!("Ignoring timeout because the request was dropped: {:?}", error);
+            }
+        }
+    });
+
     Ok(rx)
 }
 
-pub async fn send_ws_notification(notification: WsNotification, context_ref: &AppContextRef) -> Result<(), Box<dyn Error + Send>> {
-    send_ws_message(WsMessage::Notification(notification), context_ref).await
+/// Withdraws a request sent to the client with `connection_id`, resolving its receiver
+/// with a `Cancelled` error and notifying the client to stop processing it.
+pub async fn cancel_ws_request(connection_id: &Arc<String>, request_id: &Arc<String>, context_ref: &AppContextRef) -> Result<(), Box<dyn Error + Send>> {
+    let tx = match context_ref.lock().await.client_mut(connection_id) {
+        Some(client) => client.pending_answers.remove(request_id),
+        None => return Err(Box::new(ClientDisconnectedError {})),
+    };
+
+    // Ignore requests already answered.
+    let tx = match tx {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+
+    let error = WsError::new_string(request_id.clone(), WsErrorId::Cancelled, "Request cancelled".to_string());
+    if let Err(error) = tx.send(Err(error)) {
+        trace!("Ignoring cancellation because the request was dropped: {:?}", error);
+    }
+
+    let notification = WsNotification::new(WsNotificationMethod::Cancel(WsCancelParams { id: request_id.clone() }));
+    send_ws_notification(connection_id, notification, context_ref).await
+}
+
+/// Sends a notification to the client with `connection_id`.
+pub async fn send_ws_notification(connection_id: &Arc<String>, notification: WsNotification, context_ref: &AppContextRef) -> Result<(), Box<dyn Error + Send>> {
+    send_ws_message(connection_id, WsMessage::Notification(notification), context_ref).await
 }
 
-async fn send_ws_message(message: WsMessage, context_ref: &AppContextRef) -> Result<(), Box<dyn Error + Send>> {
-    let json_response = serde_json::to_string(&message).unwrap();
-    let response_message = Ok(Message::text(json_response));
+/// Sends a notification to every connected client.
+pub async fn broadcast_ws_notification(notification: WsNotification, context_ref: &AppContextRef) {
+    trace!("Broadcasting message: {:?}", &notification);
 
-    let context = context_ref.lock().await;
+    let frame = WsFrame::Single(WsMessage::Notification(notification));
+    let connection_ids: Vec<Arc<String>> = context_ref.lock().await.clients.keys().cloned().collect();
 
-    if let Some(client) = context.client.as_ref() {
-        trace!("Sending message: {:?}", &message);
+    for connection_id in connection_ids {
+        if let Err(e) = send_ws_frame(&connection_id, &frame, context_ref).await {
+            error!("Error while broadcasting message to {}: {}", connection_id, e);
+        }
+    }
+}
 
-        match client.sender.send(response_message) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(Box::new(e))
+/// Sends a notification to every client subscribed to `topic`.
+pub async fn publish_ws_notification(topic: &str, notification: WsNotification, context_ref: &AppContextRef) {
+    trace!("Publishing message in {}: {:?}", topic, &notification);
+
+    let frame = WsFrame::Single(WsMessage::Notification(notification));
+    let connection_ids: Vec<Arc<String>> = context_ref.lock().await.clients.iter()
+        .filter(|(_, client)| client.is_subscribed_to(topic))
+        .map(|(connection_id, _)| connection_id.clone())
+        .collect();
+
+    for connection_id in connection_ids {
+        if let Err(e) = send_ws_frame(&connection_id, &frame, context_ref).await {
+            error!("Error while publishing message to {}: {}", connection_id, e);
         }
+    }
+}
+
+/// Closes the connection with the client telling it the reason, and stops listening to it.
+pub async fn close_ws_connection(connection_id: &Arc<String>, reason: WsCloseReason, context_ref: &AppContextRef) -> Result<(), Box<dyn Error + Send>> {
+    let mut context = context_ref.lock().await;
+
+    if let Some(client) = context.client_mut(connection_id) {
+        client.close(reason);
+        Ok(())
     } else {
         Err(Box::new(ClientDisconnectedError {}))
     }
-}
\ No newline at end of file
+}
+
+async fn send_ws_message(connection_id: &Arc<String>, message: WsMessage, context_ref: &AppContextRef) -> Result<(), Box<dyn Error + Send>> {
+    trace!("Sending message to {}: {:?}", connection_id, &message);
+
+    send_ws_frame(connection_id, &WsFrame::Single(message), context_ref).await
+}
+
+/// Sends several messages in a single frame.
+async fn send_ws_messages(connection_id: &Arc<String>, messages: Vec<WsMessage>, context_ref: &AppContextRef) -> Result<(), Box<dyn Error + Send>> {
+    trace!("Sending messages to {}: {:?}", connection_id, &messages);
+
+    send_ws_frame(connection_id, &WsFrame::Batch(messages), context_ref).await
+}
+
+/// Queues a frame for the client. Under the `Block` policy, it waits until the client
+/// has room for it.
+async fn send_ws_frame(connection_id: &Arc<String>, frame: &WsFrame, context_ref: &AppContextRef) -> Result<(), Box<dyn Error + Send>> {
+    let mut space_ready: Option<Arc<Notify>> = None;
+
+    loop {
+        let mut context = context_ref.lock().await;
+
+        let client = match context.client_mut(connection_id) {
+            Some(v) => v,
+            None => {
+                // Wake the next producer blocked by the client.
+                if let Some(space_ready) = space_ready {
+                    space_ready.notify();
+                }
+
+                return Err(Box::new(ClientDisconnectedError {}));
+            }
+        };
+
+        let msg = match client.encode(frame) {
+            Ok(v) => v,
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        match client.send(msg, frame.is_notification()) {
+            Ok(_) => return Ok(()),
+            Err(e) if client.outbox.policy() != WsOutboxPolicy::Block => return Err(Box::new(e)),
+            Err(_) => {
+                let notify = client.outbox.space_ready.clone();
+                std::mem::drop(context);
+
+                notify.notified().await;
+                space_ready = Some(notify);
+            }
+        }
+    }
+}
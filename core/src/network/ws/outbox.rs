@@ -0,0 +1,313 @@
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use futures::SinkExt;
+use futures::stream::SplitSink;
+use tokio::sync::Notify;
+use warp::ws::{Message, WebSocket};
+
+use crate::application::AppContextRef;
+use crate::network::ws::errors::OutboxFullError;
+
+/// What to do with a frame for a client whose outbound queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WsOutboxPolicy {
+    // Wait until the client makes room for it.
+    Block,
+    // Forget the oldest queued notification to make room for it.
+    DropOldest,
+    // Close the connection with the client.
+    Disconnect,
+}
+
+impl FromStr for WsOutboxPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "block" => Ok(WsOutboxPolicy::Block),
+            "drop-oldest" => Ok(WsOutboxPolicy::DropOldest),
+            "disconnect" => Ok(WsOutboxPolicy::Disconnect),
+            _ => Err(format!("Unknown outbound queue policy: {}", s)),
+        }
+    }
+}
+
+/// The frames waiting to be written in the websocket of a client. Frames are queued while
+/// the client is disconnected, so that they are sent when it resumes its session.
+pub struct WsOutbox {
+    // Frames written before the queue that are not part of the session: the `hello`,
+    // the replayed frames and control frames.
+    priority: VecDeque<Message>,
+    // Frames of the session with whether they are notifications.
+    queue: VecDeque<(Message, bool)>,
+    // The maximum number of frames in the queue.
+    capacity: usize,
+    policy: WsOutboxPolicy,
+    // Wakes the writer of the websocket when a frame is queued.
+    pub frame_ready: Arc<Notify>,
+    // Wakes a blocked producer when a frame leaves the queue.
+    pub space_ready: Arc<Notify>,
+    // Whether the writer of the websocket is writing the last popped frame.
+    writing: bool,
+    // Wakes those waiting for every frame to be written.
+    pub flushed: Arc<Notify>,
+    // The maximum number of frames the queue has held.
+    max_depth: usize,
+    // The number of notifications dropped because the queue was full.
+    dropped: u64,
+}
+
+impl WsOutbox {
+    pub fn new(capacity: usize, policy: WsOutboxPolicy) -> WsOutbox {
+        WsOutbox {
+            priority: VecDeque::new(),
+            queue: VecDeque::new(),
+            capacity,
+            policy,
+            frame_ready: Arc::new(Notify::new()),
+            space_ready: Arc::new(Notify::new()),
+            writing: false,
+            flushed: Arc::new(Notify::new()),
+            max_depth: 0,
+            dropped: 0,
+        }
+    }
+
+    pub fn policy(&self) -> WsOutboxPolicy {
+        self.policy
+    }
+
+    pub fn depth(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Whether every queued frame has been written in the websocket.
+    pub fn is_flushed(&self) -> bool {
+        !self.writing && self.priority.is_empty() && self.queue.is_empty()
+    }
+
+    /// Queues a frame applying the policy if the queue is full. Frames that are not
+    /// notifications are queued even if there is no notification to drop.
+    pub fn push(&mut self, msg: Message, is_notification: bool) -> Result<(), OutboxFullError> {
+        if self.queue.len() >= self.capacity {
+            match self.policy {
+                WsOutboxPolicy::Block | WsOutboxPolicy::Disconnect => return Err(OutboxFullError {}),
+                WsOutboxPolicy::DropOldest => {
+                    let oldest = self.queue.iter().position(|(_, is_notification)| *is_notification);
+                    match oldest {
+                        Some(index) => {
+                            self.queue.remove(index);
+                            self.dropped += 1;
+                        }
+                        None if is_notification => {
+                            self.dropped += 1;
+                            return Ok(());
+                        }
+                        None => {}
+                    }
+                }
+            }
+        }
+
+        self.queue.push_back((msg, is_notification));
+        self.max_depth = self.max_depth.max(self.queue.len());
+        self.frame_ready.notify();
+        Ok(())
+    }
+
+    /// Writes a frame before the queued ones.
+    pub fn push_priority(&mut self, msg: Message) {
+        self.priority.push_back(msg);
+        self.frame_ready.notify();
+    }
+
+    /// Replaces the priority frames of a previous connection with those of a new one.
+    pub fn reset_priority(&mut self, frames: Vec<Message>) {
+        self.priority = frames.into();
+        self.frame_ready.notify();
+    }
+
+    /// The next frame to write with whether it belongs to the session. Popping again
+    /// means the previous frame has been written.
+    pub fn pop(&mut self) -> Option<(Message, bool)> {
+        let frame = match self.priority.pop_front() {
+            Some(msg) => Some((msg, false)),
+            None => self.queue.pop_front().map(|(msg, _)| {
+                self.space_ready.notify();
+                (msg, true)
+            }),
+        };
+
+        if self.writing && frame.is_none() {
+            self.flushed.notify();
+        }
+
+        self.writing = frame.is_some();
+        frame
+    }
+
+    /// Forgets all the queued frames.
+    pub fn clear(&mut self) {
+        self.priority.clear();
+        self.queue.clear();
+    }
+}
+
+/// Waits until the frames queued for the client with `connection_id` have been written
+/// in its websocket, or the client is gone.
+pub async fn wait_outbox_flushed(connection_id: &Arc<String>, context_ref: &AppContextRef) {
+    loop {
+        let flushed = {
+            let context = context_ref.lock().await;
+            match context.client(connection_id) {
+                Some(client) if !client.outbox.is_flushed() => client.outbox.flushed.clone(),
+                _ => return,
+            }
+        };
+
+        flushed.notified().await;
+    }
+}
+
+/// Writes the frames of the outbox of the client with `connection_id` in its websocket,
+/// numbering those that belong to the session. It stops when the connection identified
+/// by `socket_id` is replaced, the websocket fails or a close frame is sent.
+pub async fn run_outbox_writer(connection_id: Arc<String>, socket_id: Arc<String>, mut sink: SplitSink<WebSocket, Message>, context_ref: AppContextRef) {
+    loop {
+        let (frame, frame_ready) = {
+            let mut context = context_ref.lock().await;
+            let client = match context.client_mut(&connection_id) {
+                Some(v) if v.socket_id == socket_id => v,
+                _ => return,
+            };
+
+            let frame = client.outbox.pop();
+            if let Some((msg, true)) = &frame {
+                client.session.push(msg.clone());
+            }
+
+            (frame.map(|(msg, _)| msg), client.outbox.frame_ready.clone())
+        };
+
+        match frame {
+            Some(msg) => {
+                let is_close = msg.is_close();
+                if let Err(e) = sink.send(msg).await {
+                    error!("error sending websocket msg: {}", e);
+                    return;
+                }
+
+                // Nothing can be sent after closing.
+                if is_close {
+                    return;
+                }
+            }
+            None => frame_ready.notified().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // `tokio` exports its own `test` macro.
+    use std::prelude::v1::test;
+
+    use crate::network::ws::session::WsSession;
+
+    use super::*;
+
+    fn notification(text: &str) -> (Message, bool) {
+        (Message::text(text), true)
+    }
+
+    fn response(text: &str) -> (Message, bool) {
+        (Message::text(text), false)
+    }
+
+    fn outbox(policy: WsOutboxPolicy, frames: &[(Message, bool)]) -> WsOutbox {
+        let mut outbox = WsOutbox::new(2, policy);
+        for (msg, is_notification) in frames {
+            let _ = outbox.push(msg.clone(), *is_notification);
+        }
+
+        outbox
+    }
+
+    /// Pops every frame numbering those of the session as the writer does.
+    fn drain(outbox: &mut WsOutbox) -> Vec<String> {
+        let mut session = WsSession::new(Arc::new("token".to_string()), 10);
+        while let Some((msg, true)) = outbox.pop() {
+            session.push(msg);
+        }
+
+        session.replay_from(0).unwrap().iter().map(|v| v.to_str().unwrap().to_string()).collect()
+    }
+
+    #[test]
+    fn test_block_and_disconnect_reject_when_full() {
+        for policy in [WsOutboxPolicy::Block, WsOutboxPolicy::Disconnect] {
+            let mut outbox = outbox(policy, &[notification("1"), notification("2")]);
+            assert!(outbox.push(Message::text("3"), true).is_err());
+            assert!(outbox.push(Message::text("4"), false).is_err());
+            assert_eq!(outbox.dropped(), 0);
+            assert_eq!(drain(&mut outbox), vec!["1", "2"]);
+        }
+    }
+
+    #[test]
+    fn test_drop_oldest_drops_the_oldest_notification() {
+        let mut outbox = outbox(WsOutboxPolicy::DropOldest, &[response("1"), notification("2")]);
+        assert!(outbox.push(Message::text("3"), true).is_ok());
+        assert_eq!(outbox.dropped(), 1);
+        assert_eq!(drain(&mut outbox), vec!["1", "3"]);
+    }
+
+    #[test]
+    fn test_drop_oldest_never_drops_other_frames() {
+        let mut outbox = outbox(WsOutboxPolicy::DropOldest, &[response("1"), response("2")]);
+
+        // A notification is dropped itself when there is no notification to make room for it.
+        assert!(outbox.push(Message::text("3"), true).is_ok());
+        assert_eq!(outbox.dropped(), 1);
+
+        // Other frames are queued beyond the capacity.
+        assert!(outbox.push(Message::text("4"), false).is_ok());
+        assert_eq!(outbox.dropped(), 1);
+        assert_eq!(outbox.max_depth(), 3);
+        assert_eq!(drain(&mut outbox), vec!["1", "2", "4"]);
+    }
+
+    #[test]
+    fn test_dropped_frames_get_no_seq() {
+        let mut outbox = outbox(WsOutboxPolicy::DropOldest, &[notification("1"), notification("2"), notification("3"), response("4")]);
+        assert_eq!(outbox.dropped(), 2);
+
+        let mut session = WsSession::new(Arc::new("token".to_string()), 10);
+        while let Some((msg, true)) = outbox.pop() {
+            session.push(msg);
+        }
+
+        // The kept frames are numbered 1 and 2.
+        assert!(session.replay_from(3).is_none());
+        assert_eq!(session.replay_from(1).unwrap()[0].to_str().unwrap(), "4");
+    }
+
+    #[test]
+    fn test_priority_frames_come_first_and_are_not_numbered() {
+        let mut outbox = outbox(WsOutboxPolicy::Block, &[notification("1")]);
+        outbox.push_priority(Message::text("hello"));
+        assert_eq!(outbox.pop().map(|(msg, in_session)| (msg.to_str().unwrap().to_string(), in_session)), Some(("hello".to_string(), false)));
+        assert_eq!(drain(&mut outbox), vec!["1"]);
+        assert!(outbox.is_flushed());
+    }
+}
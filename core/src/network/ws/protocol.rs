@@ -0,0 +1,150 @@
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::JsonSchema;
+use schemars::Map;
+use schemars::schema::Schema;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::auth::roles::AuthPermission;
+use crate::network::ws::messages::{WsCancelParams, WsHelloParams, WsHelloSession, WsNotificationMethod, WsProgress};
+use crate::network::ws::methods::WsMethodRegistry;
+
+/// The version of the websocket protocol implemented by the core.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// The oldest version of the protocol the core can talk to.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// The optional parts of the protocol supported by the core.
+#[cfg(not(feature = "cbor"))]
+pub const PROTOCOL_FEATURES: &[&str] = &["cancel", "progress", "topics", "msgpack", "resume"];
+
+/// The optional parts of the protocol supported by the core.
+#[cfg(feature = "cbor")]
+pub const PROTOCOL_FEATURES: &[&str] = &["cancel", "progress", "topics", "msgpack", "resume", "cbor"];
+
+pub fn is_compatible_version(version: u32) -> bool {
+    (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version)
+}
+
+/// Builds the `hello` the core sends to every client when it connects.
+pub fn server_hello(methods: &WsMethodRegistry, session: WsHelloSession) -> WsHelloParams {
+    let mut method_names: Vec<String> = methods.names().map(|v| v.to_string()).collect();
+    method_names.sort();
+
+    WsHelloParams {
+        version: PROTOCOL_VERSION,
+        features: PROTOCOL_FEATURES.iter().map(|v| v.to_string()).collect(),
+        methods: method_names,
+        session: Some(session),
+    }
+}
+
+/// Describes every request and notification method the clients can send, and the
+/// messages the server sends on its own.
+pub fn describe_protocol(methods: &WsMethodRegistry) -> WsProtocolDescription {
+    let mut generator = SchemaSettings::draft07().into_generator();
+    let requests = methods.describe(&mut generator);
+    let notifications = describe_notifications(&mut generator);
+
+    let server_messages = vec![
+        WsServerMessageDescription {
+            message_type: "not".to_string(),
+            method: Some("hello".to_string()),
+            schema: generator.subschema_for::<WsHelloParams>(),
+        },
+        WsServerMessageDescription {
+            message_type: "not".to_string(),
+            method: Some("cancel".to_string()),
+            schema: generator.subschema_for::<WsCancelParams>(),
+        },
+        WsServerMessageDescription {
+            message_type: "prg".to_string(),
+            method: None,
+            schema: generator.subschema_for::<WsProgress>(),
+        },
+    ];
+
+    WsProtocolDescription {
+        version: PROTOCOL_VERSION,
+        features: PROTOCOL_FEATURES.iter().map(|v| v.to_string()).collect(),
+        requests,
+        notifications,
+        server_messages,
+        definitions: generator.take_definitions(),
+    }
+}
+
+/// Describes the variants of `WsNotificationMethod` from its schema, where each one is
+/// an object with the name in `method` and the schema of its params in `params`.
+fn describe_notifications(generator: &mut SchemaGenerator) -> Vec<WsMethodDescription> {
+    let variants = WsNotificationMethod::json_schema(generator).into_object().subschemas.and_then(|v| v.one_of).unwrap_or_default();
+    let mut notifications: Vec<WsMethodDescription> = variants.into_iter().filter_map(|variant| {
+        let mut properties = variant.into_object().object?.properties;
+        let name = properties.remove("method")?.into_object().enum_values?.first()?.as_str()?.to_string();
+
+        Some(WsMethodDescription {
+            name,
+            sequential: None,
+            permission: None,
+            params: properties.remove("params")?,
+            result: None,
+        })
+    }).collect();
+
+    notifications.sort_by(|a, b| a.name.cmp(&b.name));
+    notifications
+}
+
+#[derive(Serialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WsProtocolDescription {
+    pub version: u32,
+    pub features: Vec<String>,
+    pub requests: Vec<WsMethodDescription>,
+    pub notifications: Vec<WsMethodDescription>,
+
+    // The messages the server sends besides the answers to the requests.
+    pub server_messages: Vec<WsServerMessageDescription>,
+
+    // The schemas referenced by the methods as `#/definitions/<name>`.
+    #[schemars(with = "Value")]
+    pub definitions: Map<String, Schema>,
+}
+
+#[derive(Serialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WsMethodDescription {
+    pub name: String,
+
+    // Whether the requests are processed one after another. Only for request methods.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sequential: Option<bool>,
+
+    // What is needed to call the method. Only for request methods.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub permission: Option<AuthPermission>,
+
+    #[schemars(with = "Value")]
+    pub params: Schema,
+
+    // Only request methods have a result.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<Value>")]
+    pub result: Option<Schema>,
+}
+
+#[derive(Serialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WsServerMessageDescription {
+    #[serde(rename = "type")]
+    pub message_type: String,
+
+    // Only for notifications.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+
+    // The params of notifications, or the fields of the message besides `type` otherwise.
+    #[schemars(with = "Value")]
+    pub schema: Schema,
+}
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::application::AppClient;
+use crate::network::ws::messages::{WsCloseReason, WsError, WsErrorId, WsRequest};
+use crate::network::ws::middleware::{WsMiddleware, WsRequestContext};
+
+/// How many requests a client can make: up to `burst` at once, refilling at `rate` per second.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WsRateLimit {
+    // The requests per second.
+    pub rate: f64,
+    // The requests that can be made at once.
+    pub burst: f64,
+}
+
+impl FromStr for WsRateLimit {
+    type Err = String;
+
+    /// Parses `<rate>/<burst>`, or `<rate>` for a burst of the same size.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (rate, burst) = match s.split_once('/') {
+            Some((rate, burst)) => (rate, Some(burst)),
+            None => (s, None),
+        };
+
+        let rate: f64 = rate.trim().parse().ok().filter(|v: &f64| v.is_finite() && *v > 0.0).ok_or_else(|| format!("Invalid rate: {}", rate))?;
+        let burst: f64 = match burst {
+            Some(burst) => burst.trim().parse().ok().filter(|v: &f64| v.is_finite() && *v >= 1.0).ok_or_else(|| format!("Invalid burst: {}", burst))?,
+            None => rate.max(1.0),
+        };
+
+        Ok(WsRateLimit { rate, burst })
+    }
+}
+
+/// The limits of every client, for all its requests and for those to specific methods.
+#[derive(Debug, Clone, Default)]
+pub struct WsRateLimits {
+    pub global: Option<WsRateLimit>,
+    pub methods: HashMap<String, WsRateLimit>,
+}
+
+impl WsRateLimits {
+    /// Parses the per method limits from `<method>=<limit>,<method>=<limit>...`.
+    pub fn parse_methods(s: &str) -> Result<HashMap<String, WsRateLimit>, String> {
+        s.split(',').map(|v| v.trim()).filter(|v| !v.is_empty()).map(|entry| {
+            let (method, limit) = entry.split_once('=').ok_or_else(|| format!("Invalid method limit: {}", entry))?;
+            Ok((method.trim().to_string(), limit.parse()?))
+        }).collect()
+    }
+}
+
+/// What to do with a client that exceeds its rate limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WsRateLimitPolicy {
+    // Answer the request with a `RateLimited` error.
+    Reject,
+    // Also close the connection with the client.
+    Disconnect,
+}
+
+impl FromStr for WsRateLimitPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(WsRateLimitPolicy::Reject),
+            "disconnect" => Ok(WsRateLimitPolicy::Disconnect),
+            _ => Err(format!("Unknown rate limit policy: {}", s)),
+        }
+    }
+}
+
+struct WsTokenBucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl WsTokenBucket {
+    fn new(limit: &WsRateLimit, now: Instant) -> WsTokenBucket {
+        WsTokenBucket {
+            tokens: limit.burst,
+            refilled_at: now,
+        }
+    }
+
+    fn refill(&mut self, limit: &WsRateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.rate).min(limit.burst);
+        self.refilled_at = now;
+    }
+}
+
+/// The token buckets of a client. They are created the first time they are needed.
+#[derive(Default)]
+pub struct WsRateLimiter {
+    global: Option<WsTokenBucket>,
+    methods: HashMap<Arc<String>, WsTokenBucket>,
+}
+
+impl WsRateLimiter {
+    /// Takes a token from the global bucket and from the one of `method` returning whether
+    /// the request is allowed. Nothing is taken if any of them is empty.
+    pub fn take(&mut self, limits: &WsRateLimits, method: &Arc<String>, now: Instant) -> bool {
+        let WsRateLimiter { global, methods } = self;
+        let global = limits.global.as_ref().map(|limit| {
+            let bucket = global.get_or_insert_with(|| WsTokenBucket::new(limit, now));
+            bucket.refill(limit, now);
+            bucket
+        });
+
+        let method = limits.methods.get(method.as_str()).map(|limit| {
+            let bucket = methods.entry(method.clone()).or_insert_with(|| WsTokenBucket::new(limit, now));
+            bucket.refill(limit, now);
+            bucket
+        });
+
+        let buckets: Vec<&mut WsTokenBucket> = global.into_iter().chain(method).collect();
+        if buckets.iter().any(|bucket| bucket.tokens < 1.0) {
+            return false;
+        }
+
+        for bucket in buckets {
+            bucket.tokens -= 1.0;
+        }
+
+        true
+    }
+}
+
+/// Limits the requests of every client, answering those that exceed the limits with
+/// a `RateLimited` error.
+pub struct WsRateLimitMiddleware {
+    limits: WsRateLimits,
+    policy: WsRateLimitPolicy,
+}
+
+impl WsRateLimitMiddleware {
+    pub fn new(limits: WsRateLimits, policy: WsRateLimitPolicy) -> WsRateLimitMiddleware {
+        WsRateLimitMiddleware {
+            limits,
+            policy,
+        }
+    }
+}
+
+impl WsMiddleware for WsRateLimitMiddleware {
+    fn before(&self, connection_id: &Arc<String>, client: &mut AppClient, request: &WsRequest, _request_context: &mut WsRequestContext) -> Result<(), WsError> {
+        if client.rate_limiter.take(&self.limits, &request.method, Instant::now()) {
+            return Ok(());
+        }
+
+        warn!("Client {} exceeded the rate limit calling {}", connection_id, request.method);
+
+        if self.policy == WsRateLimitPolicy::Disconnect {
+            client.close(WsCloseReason::RateLimited);
+        }
+
+        Err(WsError::new_string(request.id.clone(), WsErrorId::RateLimited, format!("Too many requests to {}", request.method)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // `tokio` exports its own `test` macro.
+    use std::prelude::v1::test;
+    use std::time::Duration;
+
+    use super::*;
+
+    fn limit(rate: f64, burst: f64) -> WsRateLimit {
+        WsRateLimit { rate, burst }
+    }
+
+    #[test]
+    fn test_parse_limit() {
+        assert_eq!("5/10".parse(), Ok(limit(5.0, 10.0)));
+        assert_eq!(" 5 / 10 ".parse(), Ok(limit(5.0, 10.0)));
+        assert_eq!("5".parse(), Ok(limit(5.0, 5.0)));
+        assert_eq!("0.5".parse(), Ok(limit(0.5, 1.0)));
+    }
+
+    #[test]
+    fn test_parse_invalid_limit() {
+        for s in &["0/0", "0/10", "5/0", "5/", "/5", "-1/5", "abc", "5/abc", "inf/5", ""] {
+            assert!(s.parse::<WsRateLimit>().is_err(), "{} should be invalid", s);
+        }
+    }
+
+    #[test]
+    fn test_parse_method_limits() {
+        let methods = WsRateLimits::parse_methods(" echo=5/10 , ping=1,").unwrap();
+        assert_eq!(methods.len(), 2);
+        assert_eq!(methods["echo"], limit(5.0, 10.0));
+        assert_eq!(methods["ping"], limit(1.0, 1.0));
+        assert!(WsRateLimits::parse_methods("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_invalid_method_limits() {
+        for s in &["echo", "echo=", "echo=5/", "echo=5/10,ping"] {
+            assert!(WsRateLimits::parse_methods(s).is_err(), "{} should be invalid", s);
+        }
+    }
+
+    #[test]
+    fn test_bucket_refill() {
+        let limits = WsRateLimits { global: Some(limit(2.0, 2.0)), methods: HashMap::new() };
+        let method = Arc::new("echo".to_string());
+        let mut limiter = WsRateLimiter::default();
+        let start = Instant::now();
+
+        assert!(limiter.take(&limits, &method, start));
+        assert!(limiter.take(&limits, &method, start));
+        assert!(!limiter.take(&limits, &method, start));
+
+        // Half a second refills one token.
+        let later = start + Duration::from_millis(500);
+        assert!(limiter.take(&limits, &method, later));
+        assert!(!limiter.take(&limits, &method, later));
+
+        // The bucket never holds more than the burst.
+        let much_later = later + Duration::from_secs(60);
+        assert!(limiter.take(&limits, &method, much_later));
+        assert!(limiter.take(&limits, &method, much_later));
+        assert!(!limiter.take(&limits, &method, much_later));
+    }
+
+    #[test]
+    fn test_method_bucket() {
+        let mut methods = HashMap::new();
+        methods.insert("echo".to_string(), limit(1.0, 1.0));
+        let limits = WsRateLimits { global: Some(limit(1.0, 2.0)), methods };
+        let echo = Arc::new("echo".to_string());
+        let ping = Arc::new("ping".to_string());
+        let mut limiter = WsRateLimiter::default();
+        let now = Instant::now();
+
+        assert!(limiter.take(&limits, &echo, now));
+        assert!(!limiter.take(&limits, &echo, now));
+
+        // The rejected request took no token from the global bucket.
+        assert!(limiter.take(&limits, &ping, now));
+        assert!(!limiter.take(&limits, &ping, now));
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Instant;
+
+use warp::ws::Message;
+
+/// What a client presents when reconnecting to resume its session.
+#[derive(Debug)]
+pub struct WsResumeParams {
+    // The token of the session, received in the `hello` of the server.
+    pub token: String,
+    // The sequence number of the last frame the client received.
+    pub last_seq: u64,
+}
+
+/// The part of a client that survives its connections. Every data frame sent to the
+/// client, except the `hello`, gets the next sequence number and is kept until the
+/// client acknowledges it, so that it can be replayed if the connection drops.
+pub struct WsSession {
+    // The secret the client presents to resume the session.
+    pub token: Arc<String>,
+    // The sequence number of the last frame sent.
+    seq: u64,
+    // The frames not acknowledged by the client yet with their sequence numbers.
+    buffer: VecDeque<(u64, Message)>,
+    // The maximum number of frames kept in the buffer.
+    buffer_size: usize,
+    // When the client lost its connection, if it has not resumed the session yet.
+    pub disconnected_at: Option<Instant>,
+}
+
+impl WsSession {
+    pub fn new(token: Arc<String>, buffer_size: usize) -> WsSession {
+        WsSession {
+            token,
+            seq: 0,
+            buffer: VecDeque::new(),
+            buffer_size,
+            disconnected_at: None,
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.disconnected_at.is_none()
+    }
+
+    /// Numbers a frame and keeps it until it is acknowledged, forgetting the oldest
+    /// frame if the buffer is full.
+    pub fn push(&mut self, msg: Message) {
+        self.seq += 1;
+
+        if self.buffer_size == 0 {
+            return;
+        }
+
+        if self.buffer.len() == self.buffer_size {
+            self.buffer.pop_front();
+        }
+
+        self.buffer.push_back((self.seq, msg));
+    }
+
+    /// Forgets the frames the client has received up to `seq`.
+    pub fn acknowledge(&mut self, seq: u64) {
+        while let Some((first_seq, _)) = self.buffer.front() {
+            if *first_seq > seq {
+                break;
+            }
+
+            self.buffer.pop_front();
+        }
+    }
+
+    /// The frames sent after `last_seq`, or `None` if some of them are not in the buffer anymore.
+    pub fn replay_from(&self, last_seq: u64) -> Option<Vec<Message>> {
+        if last_seq > self.seq {
+            return None;
+        }
+
+        if last_seq < self.seq {
+            match self.buffer.front() {
+                Some((first_seq, _)) if *first_seq <= last_seq + 1 => {}
+                _ => return None,
+            }
+        }
+
+        Some(self.buffer.iter().filter(|(seq, _)| *seq > last_seq).map(|(_, msg)| msg.clone()).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // `tokio` exports its own `test` macro.
+    use std::prelude::v1::test;
+
+    use super::*;
+
+    fn session(buffer_size: usize, frames: usize) -> WsSession {
+        let mut session = WsSession::new(Arc::new("token".to_string()), buffer_size);
+        for seq in 1..=frames {
+            session.push(Message::text(seq.to_string()));
+        }
+
+        session
+    }
+
+    fn texts(frames: Vec<Message>) -> Vec<String> {
+        frames.iter().map(|v| v.to_str().unwrap().to_string()).collect()
+    }
+
+    #[test]
+    fn test_resume_inside_buffer() {
+        let session = session(10, 5);
+        assert_eq!(texts(session.replay_from(2).unwrap()), vec!["3", "4", "5"]);
+        assert_eq!(texts(session.replay_from(0).unwrap()), vec!["1", "2", "3", "4", "5"]);
+        assert!(session.replay_from(5).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_resume_past_trimmed_frame() {
+        // Only the frames 3 to 5 are kept.
+        let session = session(3, 5);
+        assert_eq!(texts(session.replay_from(2).unwrap()), vec!["3", "4", "5"]);
+        assert!(session.replay_from(1).is_none());
+        assert!(session.replay_from(0).is_none());
+    }
+
+    #[test]
+    fn test_resume_ahead_of_server() {
+        let session = session(10, 5);
+        assert!(session.replay_from(6).is_none());
+    }
+
+    #[test]
+    fn test_acknowledge() {
+        let mut session = session(10, 5);
+        session.acknowledge(3);
+        assert_eq!(texts(session.replay_from(3).unwrap()), vec!["4", "5"]);
+        assert!(session.replay_from(2).is_none());
+    }
+
+    #[test]
+    fn test_acknowledge_beyond_last_seq() {
+        let mut session = session(10, 5);
+        session.acknowledge(100);
+        assert!(session.replay_from(5).unwrap().is_empty());
+        assert!(session.replay_from(4).is_none());
+
+        // The frames sent afterwards are kept.
+        session.push(Message::text("6"));
+        assert_eq!(texts(session.replay_from(5).unwrap()), vec!["6"]);
+    }
+}
@@ -0,0 +1,306 @@
+//! Generates the TypeScript definitions of the websocket protocol used by the app
+//! from the Rust types, so that both sides cannot drift apart.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use schemars::gen::SchemaSettings;
+use schemars::schema::{InstanceType, Schema, SchemaObject, SingleOrVec};
+
+use crate::network::ws::messages::WsErrorId;
+use crate::network::ws::methods::WsMethodRegistry;
+use crate::network::ws::protocol::{describe_protocol, WsMethodDescription};
+
+const DEFAULT_OUTPUT_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../app/src/types/api");
+const TYPES_FILE: &str = "WebsocketTypes.ts";
+const METHODS_FILE: &str = "WebsocketMethods.ts";
+const HEADER: &str = "// Generated by `cargo run --features typescript -- typescript` in `core`. Do not edit.\n\n";
+
+const TYPES_TEMPLATE: &str = r#"export type WebsocketMessage<T> = WebsocketRequest<T> | WebsocketNotification<T> | WebsocketResponseOrError<T>
+export type WebsocketResponseOrError<T> = WebsocketResponse<T> | WebsocketError | WebsocketProgress<T>
+
+export interface WebsocketRequest<T> {
+    type: "req",
+    id: string,
+    method: string,
+    params: T,
+}
+
+export interface WebsocketNotification<T> {
+    type: "not",
+    method: string,
+    params: T,
+}
+
+export interface WebsocketResponse<T> {
+    type: "res",
+    id: string,
+    result: T,
+}
+
+export interface WebsocketProgress<T> {
+    type: "prg",
+    id: string,
+    progress?: number,
+    message?: string,
+    partial?: T,
+}
+
+export interface WebsocketError {
+    type: "err",
+    id?: string,
+    eid: WebsocketErrorId,
+    message: string,
+    data?: any,
+}
+
+export function isWebsocketError(obj: any): obj is WebsocketError {
+    return obj.type === "err";
+}
+"#;
+
+const METHODS_TEMPLATE: &str = r#"export class WebsocketSystemMethod<T> {
+    name: string;
+
+    // CONSTRUCTORS -----------------------------------------------------------
+
+    constructor(name: string) {
+        this.name = name;
+    }
+}
+
+export class WebsocketRequestMethod<P, R> {
+    name: string;
+
+    // CONSTRUCTORS -----------------------------------------------------------
+
+    constructor(name: string) {
+        this.name = name;
+    }
+}
+
+export class WebsocketNotificationMethod<P> {
+    name: string;
+
+    // CONSTRUCTORS -----------------------------------------------------------
+
+    constructor(name: string) {
+        this.name = name;
+    }
+}
+"#;
+
+const SYSTEM_METHODS: &str = r#"    sys: {
+        open: new WebsocketSystemMethod<Event>("open"),
+        close: new WebsocketSystemMethod<CloseEvent>("close"),
+        error: new WebsocketSystemMethod<Event>("error"),
+        unhandledRequest: new WebsocketSystemMethod<WebsocketRequest<any>>("unhandled_request"),
+        unhandledResponse: new WebsocketSystemMethod<WebsocketResponse<any>>("unhandled_response"),
+        unhandledError: new WebsocketSystemMethod<WebsocketError>("unhandled_error"),
+        progress: new WebsocketSystemMethod<WebsocketProgress<any>>("progress"),
+        incorrectMessage: new WebsocketSystemMethod<WebsocketMessage<any>>("incorrect_message"),
+    },
+"#;
+
+const SYSTEM_IMPORTS: &[&str] = &["WebsocketError", "WebsocketMessage", "WebsocketProgress", "WebsocketRequest", "WebsocketResponse"];
+
+/// Runs the generator with the command line arguments after `typescript`:
+/// `[--check] [<output dir>]`. In check mode the files are not written and the
+/// process fails if they are not up to date.
+pub fn run(args: &[String]) {
+    let check = args.iter().any(|v| v == "--check");
+    let output_dir = args.iter().find(|v| !v.starts_with("--")).map(PathBuf::from).unwrap_or_else(|| PathBuf::from(DEFAULT_OUTPUT_DIR));
+
+    let mut stale = false;
+    for (file_name, content) in generate() {
+        let path = output_dir.join(file_name);
+
+        if check {
+            if fs::read_to_string(&path).ok().as_deref() != Some(content.as_str()) {
+                error!("{} is not up to date", path.display());
+                stale = true;
+            }
+        } else if let Err(e) = write_file(&path, &content) {
+            error!("Cannot write {}: {}", path.display(), e);
+            std::process::exit(1);
+        } else {
+            info!("Generated {}", path.display());
+        }
+    }
+
+    if stale {
+        error!("Run `cargo run --features typescript -- typescript` in `core` to update the TypeScript definitions");
+        std::process::exit(1);
+    }
+}
+
+fn write_file(path: &Path, content: &str) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    fs::write(path, content)
+}
+
+/// The content of every generated file by its name.
+pub fn generate() -> Vec<(&'static str, String)> {
+    let description = describe_protocol(&WsMethodRegistry::with_defaults());
+    let error_ids = SchemaSettings::draft07().into_generator().into_root_schema_for::<WsErrorId>();
+
+    // Types.
+    let mut types = String::from(HEADER);
+    types.push_str(TYPES_TEMPLATE);
+    types.push_str("\n// ERRORS ---------------------------------------------------------------------\n\n");
+    types.push_str(&format!("export type WebsocketErrorId = {};\n", ts_object_type(&error_ids.schema, &mut BTreeSet::new())));
+    types.push_str("\n// PROTOCOL -------------------------------------------------------------------\n");
+
+    for (name, schema) in &description.definitions {
+        types.push('\n');
+        types.push_str(&ts_definition(name, schema));
+    }
+
+    // Methods.
+    let mut references = BTreeSet::new();
+    let mut messages = String::new();
+    for method in &description.requests {
+        let params = ts_params_type(method, &mut references);
+        let result = method.result.as_ref().map(|v| ts_type(v, &mut references)).unwrap_or_else(|| "any".to_string());
+        messages.push_str(&format!("        {}: new WebsocketRequestMethod<{}, {}>(\"{}\"),\n", ts_property_name(&method.name), params, result, method.name));
+    }
+
+    for method in &description.notifications {
+        let params = ts_params_type(method, &mut references);
+        messages.push_str(&format!("        {}: new WebsocketNotificationMethod<{}>(\"{}\"),\n", ts_property_name(&method.name), params, method.name));
+    }
+
+    let mut imports: Vec<&str> = SYSTEM_IMPORTS.to_vec();
+    imports.extend(references.iter().map(|v| v.as_str()));
+    imports.sort_unstable();
+
+    let mut methods = String::from(HEADER);
+    methods.push_str(&format!("import {{\n    {},\n}} from \"src/types/api/WebsocketTypes\";\n\n", imports.join(", ")));
+    methods.push_str(METHODS_TEMPLATE);
+    methods.push_str("\nexport const WsMethods = {\n");
+    methods.push_str(SYSTEM_METHODS);
+    methods.push_str("    msg: {\n");
+    methods.push_str(&messages);
+    methods.push_str("    },\n};\n");
+
+    vec![(TYPES_FILE, types), (METHODS_FILE, methods)]
+}
+
+/// Methods without params are called with `undefined`.
+fn ts_params_type(method: &WsMethodDescription, references: &mut BTreeSet<String>) -> String {
+    match ts_type(&method.params, references).as_str() {
+        "null" => "undefined".to_string(),
+        v => v.to_string(),
+    }
+}
+
+/// `rpc.discover` becomes `rpcDiscover`.
+fn ts_property_name(name: &str) -> String {
+    let mut result = String::new();
+    for (index, part) in name.split(|c: char| !c.is_alphanumeric()).filter(|v| !v.is_empty()).enumerate() {
+        let mut chars = part.chars();
+        if index > 0 {
+            if let Some(first) = chars.next() {
+                result.extend(first.to_uppercase());
+            }
+        }
+
+        result.extend(chars);
+    }
+
+    result
+}
+
+fn ts_definition(name: &str, schema: &Schema) -> String {
+    let mut references = BTreeSet::new();
+    match schema {
+        Schema::Object(object) if object.object.as_ref().is_some_and(|v| !v.properties.is_empty()) => {
+            let validation = object.object.as_ref().unwrap();
+            let mut result = format!("export interface {} {{\n", name);
+            for (property, schema) in &validation.properties {
+                let optional = if validation.required.contains(property) { "" } else { "?" };
+                result.push_str(&format!("    {}{}: {},\n", property, optional, ts_type(schema, &mut references)));
+            }
+
+            result.push_str("}\n");
+            result
+        }
+        _ => format!("export type {} = {};\n", name, ts_type(schema, &mut references)),
+    }
+}
+
+fn ts_type(schema: &Schema, references: &mut BTreeSet<String>) -> String {
+    match schema {
+        Schema::Bool(true) => "any".to_string(),
+        Schema::Bool(false) => "never".to_string(),
+        Schema::Object(object) => ts_object_type(object, references),
+    }
+}
+
+fn ts_object_type(object: &SchemaObject, references: &mut BTreeSet<String>) -> String {
+    if let Some(reference) = &object.reference {
+        let name = reference.trim_start_matches("#/definitions/").to_string();
+        references.insert(name.clone());
+        return name;
+    }
+
+    if let Some(values) = &object.enum_values {
+        return values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(" | ");
+    }
+
+    if let Some(subschemas) = &object.subschemas {
+        if let Some(schemas) = subschemas.any_of.as_ref().or(subschemas.one_of.as_ref()) {
+            return schemas.iter().map(|v| ts_type(v, references)).collect::<Vec<_>>().join(" | ");
+        }
+
+        if let Some(schemas) = &subschemas.all_of {
+            return schemas.iter().map(|v| ts_type(v, references)).collect::<Vec<_>>().join(" & ");
+        }
+    }
+
+    match &object.instance_type {
+        None => "any".to_string(),
+        Some(SingleOrVec::Single(instance_type)) => ts_instance_type(instance_type, object, references),
+        Some(SingleOrVec::Vec(instance_types)) => instance_types.iter().map(|v| ts_instance_type(v, object, references)).collect::<Vec<_>>().join(" | "),
+    }
+}
+
+fn ts_instance_type(instance_type: &InstanceType, object: &SchemaObject, references: &mut BTreeSet<String>) -> String {
+    match instance_type {
+        InstanceType::Null => "null".to_string(),
+        InstanceType::Boolean => "boolean".to_string(),
+        InstanceType::Integer | InstanceType::Number => "number".to_string(),
+        InstanceType::String => "string".to_string(),
+        InstanceType::Array => match object.array.as_ref().and_then(|v| v.items.as_ref()) {
+            Some(SingleOrVec::Single(items)) => {
+                let items = ts_type(items, references);
+                if items.contains(' ') {
+                    format!("({})[]", items)
+                } else {
+                    format!("{}[]", items)
+                }
+            }
+            Some(SingleOrVec::Vec(items)) => format!("[{}]", items.iter().map(|v| ts_type(v, references)).collect::<Vec<_>>().join(", ")),
+            None => "any[]".to_string(),
+        },
+        InstanceType::Object => match object.object.as_ref() {
+            Some(validation) if !validation.properties.is_empty() => {
+                let properties: Vec<String> = validation.properties.iter().map(|(property, schema)| {
+                    let optional = if validation.required.contains(property) { "" } else { "?" };
+                    format!("{}{}: {}", property, optional, ts_type(schema, references))
+                }).collect();
+
+                format!("{{ {} }}", properties.join(", "))
+            }
+            Some(validation) => match &validation.additional_properties {
+                Some(schema) => format!("{{ [key: string]: {} }}", ts_type(schema, references)),
+                None => "{ [key: string]: any }".to_string(),
+            },
+            None => "{ [key: string]: any }".to_string(),
+        },
+    }
+}